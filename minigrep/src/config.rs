@@ -0,0 +1,469 @@
+use crate::pattern::{self, Pattern};
+use regexp::RegexBuilder;
+use std::env;
+use std::error::Error;
+use std::fmt;
+
+pub const USAGE: &str = "Usage: minigrep [OPTION]... PATTERN [FILE]...";
+
+pub const HELP: &str = "\
+Search for PATTERN in each FILE.
+
+Pattern selection:
+  -e, --regexp=PATTERN      use PATTERN as a regular expression; when given
+                            more than once, lines matching any are selected
+  -F, --fixed-strings       PATTERN is a plain string (the default)
+  -i, --ignore-case         ignore case distinctions
+  -S, --smart-case          ignore case unless PATTERN has an uppercase letter
+  -w, --word-regexp         match only whole words
+
+Output control:
+  -v, --invert-match        select non-matching lines
+  -n, --line-number         print line numbers
+  -b, --byte-offset         print the byte offset of each line
+  -A, --after-context=NUM   print NUM lines after each selected line
+  -B, --before-context=NUM  print NUM lines before each selected line
+  -C, --context=NUM         print NUM lines before and after
+  -c, --count               print only a count of selected lines
+  -m, --max-count=NUM       stop reading a file after NUM selected lines
+  -l, --files-with-matches  print only the name of the file if it matches
+  -H, --with-filename       print the file name for each match
+  -h, --no-filename         never print file names
+
+File selection:
+  -r, --recursive           search the files in directories, and their
+                            subdirectories
+
+A FILE of - is the standard input. Without FILE, the standard input is
+searched, or with -r the current directory.
+
+  -V, --version             print version information and exit
+      --help                display this help and exit
+
+Lines of any length are searched, but printed cut after 1 MiB. A regular
+expression too large to match a line in pieces only searches its first MiB,
+and reports each line it cut. Invalid UTF-8 is shown as U+FFFD.
+Case is also ignored when the CASE_INSENSITIVE environment variable is set.
+Exit status is 0 if a line is selected, 1 if none is, and 2 on error.";
+
+/// Command line arguments which cannot be understood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError {
+    pub msg: String,
+}
+
+impl UsageError {
+    fn new(msg: impl Into<String>) -> Self {
+        Self { msg: msg.into() }
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for UsageError {}
+
+/// What the command line asks for.
+pub enum Command {
+    Search(Config),
+    Help,
+    Version,
+}
+
+pub struct Config {
+    pub(crate) pattern: Pattern,
+    pub(crate) paths: Vec<String>,
+    pub(crate) recursive: bool,
+    /// Whether output lines start with the name of their file.
+    pub(crate) with_filename: bool,
+    pub(crate) invert: bool,
+    pub(crate) line_number: bool,
+    pub(crate) byte_offset: bool,
+    /// Lines of context printed after each selected line.
+    pub(crate) after_context: usize,
+    /// Lines of context printed before each selected line.
+    pub(crate) before_context: usize,
+    pub(crate) count: bool,
+    /// Stop reading a file after this many selected lines.
+    pub(crate) max_count: Option<usize>,
+    pub(crate) files_with_matches: bool,
+    pub(crate) word: bool,
+}
+
+#[derive(Default)]
+struct Flags {
+    regexp: Vec<String>,
+    fixed: bool,
+    ignore_case: bool,
+    smart_case: bool,
+    invert: bool,
+    line_number: bool,
+    byte_offset: bool,
+    after_context: Option<usize>,
+    before_context: Option<usize>,
+    context: Option<usize>,
+    count: bool,
+    max_count: Option<usize>,
+    files_with_matches: bool,
+    word: bool,
+    recursive: bool,
+    with_filename: Option<bool>,
+}
+
+impl Flags {
+    /// Sets the flag of a short option which takes no argument.
+    fn set(&mut self, c: char) -> Result<(), UsageError> {
+        match c {
+            'F' => self.fixed = true,
+            'i' => self.ignore_case = true,
+            'S' => self.smart_case = true,
+            'v' => self.invert = true,
+            'n' => self.line_number = true,
+            'b' => self.byte_offset = true,
+            'c' => self.count = true,
+            'l' => self.files_with_matches = true,
+            'w' => self.word = true,
+            'r' => self.recursive = true,
+            'H' => self.with_filename = Some(true),
+            'h' => self.with_filename = Some(false),
+            _ => return Err(UsageError::new(format!("invalid option -- '{}'", c))),
+        }
+        Ok(())
+    }
+
+    /// Sets the value of a short option which takes an argument.
+    fn set_value(&mut self, c: char, value: String) -> Result<(), UsageError> {
+        let lines = || {
+            value
+                .parse()
+                .map_err(|_| UsageError::new(format!("{}: invalid context length argument", value)))
+        };
+        match c {
+            'e' => self.regexp.push(value),
+            'm' => {
+                let max = value
+                    .parse()
+                    .map_err(|_| UsageError::new(format!("invalid max count '{}'", value)))?;
+                self.max_count = Some(max);
+            }
+            'A' => self.after_context = Some(lines()?),
+            'B' => self.before_context = Some(lines()?),
+            _ => self.context = Some(lines()?),
+        }
+        Ok(())
+    }
+}
+
+fn takes_value(c: char) -> bool {
+    matches!(c, 'e' | 'm' | 'A' | 'B' | 'C')
+}
+
+impl Command {
+    /// Reads the arguments the way GNU grep does: options and operands may
+    /// come in any order until `--`, short options may be combined as in
+    /// `-in`, and option arguments may be attached (`-ePATTERN`,
+    /// `--regexp=PATTERN`) or separate.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, UsageError> {
+        args.next();
+        let mut flags = Flags::default();
+        let mut operands = Vec::new();
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            if options_done || arg == "-" || !arg.starts_with('-') {
+                operands.push(arg);
+            } else if arg == "--" {
+                options_done = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let short = match name {
+                    "help" => Some('?'),
+                    "version" => Some('V'),
+                    "regexp" => Some('e'),
+                    "fixed-strings" => Some('F'),
+                    "ignore-case" => Some('i'),
+                    "smart-case" => Some('S'),
+                    "invert-match" => Some('v'),
+                    "line-number" => Some('n'),
+                    "count" => Some('c'),
+                    "max-count" => Some('m'),
+                    "files-with-matches" => Some('l'),
+                    "word-regexp" => Some('w'),
+                    "recursive" => Some('r'),
+                    "byte-offset" => Some('b'),
+                    "after-context" => Some('A'),
+                    "before-context" => Some('B'),
+                    "context" => Some('C'),
+                    "with-filename" => Some('H'),
+                    "no-filename" => Some('h'),
+                    _ => None,
+                };
+                match (short, value) {
+                    (None, _) => {
+                        return Err(UsageError::new(format!("unrecognized option '{}'", arg)))
+                    }
+                    (Some(c), Some(value)) if takes_value(c) => flags.set_value(c, value)?,
+                    (Some(c), None) if takes_value(c) => match args.next() {
+                        Some(value) => flags.set_value(c, value)?,
+                        None => {
+                            return Err(UsageError::new(format!(
+                                "option '--{}' requires an argument",
+                                name
+                            )))
+                        }
+                    },
+                    (Some(_), Some(_)) => {
+                        return Err(UsageError::new(format!(
+                            "option '--{}' doesn't allow an argument",
+                            name
+                        )))
+                    }
+                    (Some('?'), None) => return Ok(Command::Help),
+                    (Some('V'), None) => return Ok(Command::Version),
+                    (Some(c), None) => flags.set(c)?,
+                }
+            } else {
+                for (i, c) in arg.char_indices().skip(1) {
+                    match c {
+                        'V' => return Ok(Command::Version),
+                        c if takes_value(c) => {
+                            let rest = &arg[i + 1..];
+                            let value = if rest.is_empty() {
+                                args.next().ok_or_else(|| {
+                                    UsageError::new(format!(
+                                        "option requires an argument -- '{}'",
+                                        c
+                                    ))
+                                })?
+                            } else {
+                                rest.to_string()
+                            };
+                            flags.set_value(c, value)?;
+                            break;
+                        }
+                        c => flags.set(c)?,
+                    }
+                }
+            }
+        }
+
+        let mut operands = operands.into_iter();
+        let regexp = !flags.regexp.is_empty() && !flags.fixed;
+        let queries = if flags.regexp.is_empty() {
+            match operands.next() {
+                Some(query) => vec![query],
+                None => return Err(UsageError::new("no pattern given")),
+            }
+        } else {
+            flags.regexp
+        };
+        let mut paths = operands.collect::<Vec<_>>();
+        if paths.is_empty() {
+            paths.push(if flags.recursive { "." } else { "-" }.to_string());
+        }
+        let with_filename = flags
+            .with_filename
+            .unwrap_or(paths.len() > 1 || flags.recursive);
+
+        let ignore_case = flags.ignore_case
+            || env::var("CASE_INSENSITIVE").is_ok()
+            || (flags.smart_case
+                && !queries
+                    .iter()
+                    .any(|query| pattern::has_uppercase(query, regexp)));
+        let compile = |query: String| {
+            if !regexp {
+                return Ok(if ignore_case {
+                    Pattern::ignore_case(&query)
+                } else {
+                    Pattern::Fixed(query)
+                });
+            }
+            let build = |src: &str| {
+                RegexBuilder::new(src)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .map_err(|err| {
+                        UsageError::new(format!("invalid regular expression '{}': {}", query, err))
+                    })
+            };
+            // The query is compiled on its own first, so that its errors
+            // point into it and not into the wrapped pattern.
+            let fsm = build(&query)?;
+            match build(&pattern::line_regex(&query, flags.word)) {
+                Ok(line) => Ok(Pattern::Regex(Box::new(line))),
+                // Without the wrapping, -w would only try one match at each
+                // position.
+                Err(err) if flags.word => Err(err),
+                // The query is valid, so the wrapped automaton is too large.
+                Err(_) => Ok(Pattern::Search(Box::new(fsm))),
+            }
+        };
+        let mut patterns = queries
+            .into_iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, _>>()?;
+        let pattern = if patterns.len() == 1 {
+            patterns.remove(0)
+        } else {
+            Pattern::Any(patterns)
+        };
+        Ok(Command::Search(Config {
+            pattern,
+            paths,
+            recursive: flags.recursive,
+            with_filename,
+            invert: flags.invert,
+            line_number: flags.line_number,
+            byte_offset: flags.byte_offset,
+            after_context: flags.after_context.or(flags.context).unwrap_or(0),
+            before_context: flags.before_context.or(flags.context).unwrap_or(0),
+            count: flags.count,
+            max_count: flags.max_count,
+            files_with_matches: flags.files_with_matches,
+            word: flags.word,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, UsageError> {
+        Command::parse(["minigrep"].iter().chain(args).map(|arg| arg.to_string()))
+    }
+
+    fn search(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Command::Search(config)) => config,
+            _ => panic!("{:?} is not a search", args),
+        }
+    }
+
+    #[test]
+    fn flags() {
+        let config = search(&["-vn", "frog", "--count", "poem.txt", "-w"]);
+        assert!(matches!(&config.pattern, Pattern::Fixed(query) if query == "frog"));
+        assert_eq!(config.paths, ["poem.txt"]);
+        assert!(!config.with_filename);
+        assert!(config.invert && config.line_number && config.count && config.word);
+        assert!(!config.files_with_matches);
+
+        let config = search(&["-li", "--", "-Frog-", "-"]);
+        assert!(
+            matches!(&config.pattern, Pattern::IgnoreCase(query) if query.iter().copied().eq("-frog-".chars()))
+        );
+        assert_eq!(config.paths, ["-"]);
+        assert!(config.files_with_matches);
+
+        assert!(matches!(
+            search(&["-S", "frog", "-"]).pattern,
+            Pattern::IgnoreCase(_)
+        ));
+        assert!(matches!(
+            search(&["-S", "Frog", "-"]).pattern,
+            Pattern::Fixed(_)
+        ));
+        assert!(matches!(
+            search(&["-Si", "Frog", "-"]).pattern,
+            Pattern::IgnoreCase(_)
+        ));
+
+        assert!(matches!(parse(&["--help", "-x"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-nV"]), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn regexp() {
+        for args in [
+            &["-e", "fr.g", "poem.txt"][..],
+            &["-nefr.g", "poem.txt"],
+            &["poem.txt", "--regexp=fr.g"],
+            &["--regexp", "fr.g", "poem.txt"],
+        ] {
+            assert!(
+                matches!(search(args).pattern, Pattern::Regex(_)),
+                "{:?}",
+                args
+            );
+        }
+        let config = search(&["--regexp", "fr.g", "-F", "poem.txt"]);
+        assert!(matches!(config.pattern, Pattern::Fixed(_)));
+
+        let config = search(&["-e", "fr.g", "-enob.dy", "poem.txt"]);
+        assert_eq!(config.paths, ["poem.txt"]);
+        assert!(matches!(
+            &config.pattern,
+            Pattern::Any(patterns) if matches!(patterns[..], [Pattern::Regex(_), Pattern::Regex(_)])
+        ));
+        assert!(matches!(
+            &search(&["-SF", "-e", "frog", "-e", "Bog"]).pattern,
+            Pattern::Any(patterns) if matches!(patterns[..], [Pattern::Fixed(_), Pattern::Fixed(_)])
+        ));
+    }
+
+    #[test]
+    fn context() {
+        let config = search(&["-C2", "-A", "1", "frog"]);
+        assert_eq!((config.before_context, config.after_context), (2, 1));
+        let config = search(&["-nbB3", "--context=1", "frog"]);
+        assert_eq!((config.before_context, config.after_context), (3, 1));
+        assert!(config.line_number && config.byte_offset);
+        assert_eq!(search(&["-m3", "frog"]).max_count, Some(3));
+        assert_eq!(
+            parse(&["--max-count=-1", "frog"]).err().unwrap().msg,
+            "invalid max count '-1'"
+        );
+        assert_eq!(
+            parse(&["-A", "x", "frog"]).err().unwrap().msg,
+            "x: invalid context length argument"
+        );
+        assert_eq!(
+            parse(&["frog", "--context"]).err().unwrap().msg,
+            "option '--context' requires an argument"
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let config = search(&["frog"]);
+        assert_eq!(config.paths, ["-"]);
+        assert!(!config.recursive && !config.with_filename);
+
+        let config = search(&["-r", "frog"]);
+        assert_eq!(config.paths, ["."]);
+        assert!(config.recursive && config.with_filename);
+
+        let config = search(&["frog", "poem.txt", "-", "src"]);
+        assert_eq!(config.paths, ["poem.txt", "-", "src"]);
+        assert!(config.with_filename);
+        assert!(!search(&["-h", "frog", "poem.txt", "src"]).with_filename);
+        assert!(search(&["--with-filename", "frog"]).with_filename);
+    }
+
+    #[test]
+    fn usage_errors() {
+        for (args, msg) in [
+            (&["-x", "frog", "poem.txt"][..], "invalid option -- 'x'"),
+            (&["--frog", "poem.txt"], "unrecognized option '--frog'"),
+            (
+                &["--count=3", "frog", "poem.txt"],
+                "option '--count' doesn't allow an argument",
+            ),
+            (&["poem.txt", "-e"], "option requires an argument -- 'e'"),
+            (&[], "no pattern given"),
+            (
+                &["-e", "(frog", "poem.txt"],
+                "invalid regular expression '(frog': unclosed group at position 0",
+            ),
+        ] {
+            assert_eq!(parse(args).err().map(|err| err.msg).as_deref(), Some(msg));
+        }
+    }
+}
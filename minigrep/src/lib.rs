@@ -1,49 +1,325 @@
-use std::env;
-use std::error::Error;
-use std::fs;
-
-pub struct Config {
-    query: String,
-    filename: String,
-    case_sensitive: bool,
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::path::Path;
+
+mod config;
+mod output;
+mod pattern;
+
+pub use config::{Command, Config, UsageError, HELP, USAGE};
+use output::Printer;
+use pattern::{LineMatcher, Pattern};
+
+/// Size of the chunks files are read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of bytes of a line which are kept to be printed. A longer line is
+/// still matched as a whole, but printed cut, so that a file without
+/// newlines is not read into memory as a whole.
+const MAX_LINE: usize = 16 * CHUNK_SIZE;
+
+/// How a run went, which decides the exit status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Whether any line was selected.
+    pub selected: bool,
+    /// Number of files which could not be searched.
+    pub errors: usize,
 }
 
-impl Config {
-    pub fn new(mut args: env::Args) -> Result<Self, &'static str> {
-        args.next();
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file name"),
-        };
+/// Searches every path and prints what the flags ask for. Files which
+/// cannot be read are reported on stderr and skipped; only failing to write
+/// the output stops the run.
+pub fn run(config: Config) -> io::Result<Summary> {
+    let stdout = io::stdout();
+    let mut out = Printer::new(&config, stdout.lock());
+    let mut summary = Summary::default();
+    search_paths(&config, io::stdin().lock(), &mut out, &mut summary)?;
+    Ok(summary)
+}
 
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
-        Ok(Self {
-            query,
-            filename,
-            case_sensitive,
-        })
+/// Searches every path of `config`, reading `stdin` for `-`.
+fn search_paths(
+    config: &Config,
+    mut stdin: impl BufRead,
+    out: &mut Printer<impl Write>,
+    summary: &mut Summary,
+) -> io::Result<()> {
+    for path in config.paths.iter() {
+        if path == "-" {
+            grep(config, "(standard input)", &mut stdin, out, summary)?;
+        } else {
+            search_path(config, Path::new(path), true, out, summary)?;
+        }
     }
+    Ok(())
+}
+
+fn report(name: &str, err: io::Error, summary: &mut Summary) {
+    eprintln!("minigrep: {}: {}", name, err);
+    summary.errors += 1;
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    /* Reading a File */
-    let contents = fs::read_to_string(config.filename)?;
-    let result = if config.case_sensitive {
-        search(&config.query, &contents)
+/// Searches the file at `path`, or with `-r` the files under it. Symbolic
+/// links are followed when given on the command line (`top`), not when
+/// found while walking a directory.
+fn search_path(
+    config: &Config,
+    path: &Path,
+    top: bool,
+    out: &mut Printer<impl Write>,
+    summary: &mut Summary,
+) -> io::Result<()> {
+    let name = path.display().to_string();
+    let metadata = if top {
+        fs::metadata(path)
     } else {
-        search_case_insensitive(&config.query, &contents)
+        fs::symlink_metadata(path)
+    };
+    let file_type = match metadata {
+        Ok(metadata) => metadata.file_type(),
+        Err(err) => {
+            report(&name, err, summary);
+            return Ok(());
+        }
     };
-    for line in &result {
-        println!("{}", line);
+    if file_type.is_symlink() {
+        return Ok(());
+    }
+    if !file_type.is_dir() {
+        /* Reading a File */
+        return match File::open(path) {
+            Ok(file) => {
+                let reader = BufReader::with_capacity(CHUNK_SIZE, file);
+                grep(config, &name, reader, out, summary).map(|_| ())
+            }
+            Err(err) => {
+                report(&name, err, summary);
+                Ok(())
+            }
+        };
+    }
+    if !config.recursive {
+        eprintln!("minigrep: {}: Is a directory", name);
+        summary.errors += 1;
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    match entries {
+        Ok(mut entries) => {
+            entries.sort();
+            for entry in entries {
+                search_path(config, &entry, false, out, summary)?;
+            }
+        }
+        Err(err) => report(&name, err, summary),
     }
-    // println!("With text:\n{}", contents);
     Ok(())
 }
 
+/// Prints the selected lines read from `reader` with their context, or
+/// their count, or the file name, and returns the number of selected lines.
+///
+/// Lines are matched in pieces as they are read, and only their first
+/// `MAX_LINE` bytes are kept, so memory does not depend on the size of the
+/// input. Invalid UTF-8 is replaced by U+FFFD. Reading stops as soon as the
+/// output is known: after the first selected line with `-l`, or the context
+/// following the last one allowed by `-m`. A read error is reported and
+/// ends the file.
+fn grep(
+    config: &Config,
+    name: &str,
+    mut reader: impl BufRead,
+    out: &mut Printer<impl Write>,
+    summary: &mut Summary,
+) -> io::Result<usize> {
+    out.start();
+    let mut selected = 0;
+    let mut offset = 0;
+    let mut line = LineReader::new(config.pattern.matcher(config.word));
+    let mut number = 0;
+    loop {
+        let done = config.max_count.is_some_and(|max| selected >= max);
+        if done && (config.count || !out.wants_context()) {
+            break;
+        }
+        let len = match line.read(&mut reader) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) => {
+                report(name, err, summary);
+                break;
+            }
+        };
+        number += 1;
+        let start = offset;
+        offset += len;
+
+        if line.cut && !config.pattern.is_streamed() {
+            let msg = format!("line {} is too long, only its start was searched", number);
+            report(name, io::Error::other(msg), summary);
+        }
+        let is_selected = !done && line.matched != config.invert;
+        if is_selected {
+            selected += 1;
+            summary.selected = true;
+            if config.files_with_matches {
+                out.file_name(name)?;
+                return Ok(selected);
+            }
+        }
+        if !config.count {
+            out.line(name, number, start, &line.text, is_selected)?;
+        }
+    }
+    if config.count {
+        out.count(name, selected)?;
+    }
+    Ok(selected)
+}
+
+/// Reads lines in pieces, matching each one as a whole but keeping only
+/// its first `MAX_LINE` bytes.
+struct LineReader<'p> {
+    matcher: LineMatcher<'p>,
+    /// Bytes of a character split between two pieces, and a `\r` held back
+    /// as it may end the line.
+    pending: Vec<u8>,
+    /// The line without its line ending, invalid UTF-8 replaced by U+FFFD.
+    text: String,
+    /// Whether `text` was cut.
+    cut: bool,
+    matched: bool,
+}
+
+impl<'p> LineReader<'p> {
+    fn new(matcher: LineMatcher<'p>) -> Self {
+        Self {
+            matcher,
+            pending: Vec::new(),
+            text: String::new(),
+            cut: false,
+            matched: false,
+        }
+    }
+
+    /// Reads the next line. Returns its length with the newline, or `0` at
+    /// the end of the input.
+    fn read(&mut self, reader: &mut impl BufRead) -> io::Result<usize> {
+        self.matcher.reset();
+        self.pending.clear();
+        self.text.clear();
+        // Give back what a long line took.
+        self.text.shrink_to(CHUNK_SIZE);
+        self.cut = false;
+        let mut len = 0;
+        loop {
+            let available = match reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                if len > 0 {
+                    self.end(false);
+                }
+                return Ok(len);
+            }
+            match available.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    self.push(&available[..i]);
+                    reader.consume(i + 1);
+                    self.end(true);
+                    return Ok(len + i + 1);
+                }
+                None => {
+                    let n = available.len();
+                    self.push(available);
+                    reader.consume(n);
+                    len += n;
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.pending.is_empty() {
+            self.decode(bytes, false);
+        } else {
+            let mut data = mem::take(&mut self.pending);
+            data.extend_from_slice(bytes);
+            self.decode(&data, false);
+        }
+    }
+
+    /// Ends the line, dropping the `\r` of a `\r\n`.
+    fn end(&mut self, newline: bool) {
+        let mut rest = mem::take(&mut self.pending);
+        if newline && rest.last() == Some(&b'\r') {
+            rest.pop();
+        }
+        self.decode(&rest, true);
+        self.matched = self.matcher.finish(&self.text);
+    }
+
+    /// Decodes `data` the way `String::from_utf8_lossy` would, keeping back
+    /// what the next piece may complete unless it is the `last` one.
+    fn decode(&mut self, mut data: &[u8], last: bool) {
+        let held = !last && data.last() == Some(&b'\r');
+        if held {
+            data = &data[..data.len() - 1];
+        }
+        loop {
+            match std::str::from_utf8(data) {
+                Ok(valid) => {
+                    self.take(valid);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = data.split_at(err.valid_up_to());
+                    self.take(std::str::from_utf8(valid).unwrap());
+                    match err.error_len() {
+                        Some(len) => {
+                            self.take("\u{FFFD}");
+                            data = &rest[len..];
+                        }
+                        None if last => {
+                            self.take("\u{FFFD}");
+                            break;
+                        }
+                        None => {
+                            self.pending.extend_from_slice(rest);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if held {
+            self.pending.push(b'\r');
+        }
+    }
+
+    fn take(&mut self, piece: &str) {
+        self.matcher.feed(piece);
+        if self.cut {
+            return;
+        }
+        let mut end = piece.len().min(MAX_LINE - self.text.len());
+        while !piece.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.text.push_str(&piece[..end]);
+        self.cut = end < piece.len();
+    }
+}
+
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     // let mut result = Vec::new();
 
@@ -55,27 +331,19 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
 
     // result
 
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+    filter(&Pattern::Fixed(query.to_string()), contents)
 }
 
+/// Lines containing `query` with case folded on both sides, without
+/// building a folded copy of every line.
 pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    // let mut result = Vec::new();
-
-    // let query = query.to_lowercase();
-    // for line in contents.lines() {
-    //     if line.to_lowercase().contains(&query) {
-    //         result.push(line);
-    //     }
-    // }
-
-    // result
+    filter(&Pattern::ignore_case(query), contents)
+}
 
+fn filter<'a>(pattern: &Pattern, contents: &'a str) -> Vec<&'a str> {
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(query))
+        .filter(|line| pattern.is_match(line, false))
         .collect()
 }
 
@@ -106,5 +374,171 @@ Trust me.";
             vec!["Rust:", "Trust me."],
             search_case_insensitive(query, contents)
         );
+        assert_eq!(
+            vec!["Rust:", "Trust me."],
+            search_case_insensitive("RUST", contents)
+        );
+    }
+
+    /// Runs minigrep with `args` on the files they name, reading `input` in
+    /// small chunks for `-`, and returns what it printed and the number of
+    /// errors.
+    fn grep_with(args: &[&str], input: impl io::Read) -> (String, usize) {
+        let args = ["minigrep"].iter().chain(args);
+        let config = match Command::parse(args.map(|arg| arg.to_string())) {
+            Ok(Command::Search(config)) => config,
+            _ => unreachable!(),
+        };
+        let mut out = Vec::new();
+        let mut summary = Summary::default();
+        let stdin = BufReader::with_capacity(8, input);
+        let mut printer = Printer::new(&config, &mut out);
+        search_paths(&config, stdin, &mut printer, &mut summary).unwrap();
+        (String::from_utf8(out).unwrap(), summary.errors)
+    }
+
+    #[test]
+    fn output() {
+        let grep = |args: &[&str]| {
+            let args = args
+                .iter()
+                .chain(&["poem.txt"])
+                .copied()
+                .collect::<Vec<_>>();
+            grep_with(&args, io::empty()).0
+        };
+        assert_eq!(
+            grep(&["-n", "-e", "bod[yi]"]),
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n6:How dreary to be somebody!\n"
+        );
+        assert_eq!(grep(&["-ciw", "are"]), "2\n");
+        assert_eq!(grep(&["-vc", "o"]), "1\n");
+        assert_eq!(grep(&["-l", "frog"]), "poem.txt\n");
+        assert_eq!(grep(&["-l", "toad"]), "");
+        assert_eq!(
+            grep(&["-nw", "-e", "frog", "-e", "da?y"]),
+            "7:How public, like a frog\n8:To tell your name the livelong day\n"
+        );
+        assert_eq!(grep(&["-cF", "-e", "bog!", "-e", "?"]), "3\n");
+
+        assert_eq!(
+            grep(&["-n", "-C1", "you"]),
+            "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n3-Then there's a pair of us - don't tell!\n4:They'd banish us, you know.\n5-\n--\n7-How public, like a frog\n8:To tell your name the livelong day\n9-To an admiring bog!\n"
+        );
+        assert_eq!(
+            grep(&["-b", "-A1", "-e", "^[TH]ow"]),
+            "115:How dreary to be somebody!\n142:How public, like a frog\n166-To tell your name the livelong day\n"
+        );
+        assert_eq!(
+            grep(&["-nB2", "bog"]),
+            "7-How public, like a frog\n8-To tell your name the livelong day\n9:To an admiring bog!\n"
+        );
+    }
+
+    #[test]
+    fn recursive() {
+        let dir = std::env::temp_dir().join(format!("minigrep-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.txt"), "a frog\nno toad\n").unwrap();
+        fs::write(dir.join("sub").join("a.txt"), "frogs\n").unwrap();
+
+        let name = |path: &Path| path.display().to_string();
+        let result = grep_with(
+            &["-rc", "frog", &name(&dir), &name(&dir.join("missing"))],
+            io::empty(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            result,
+            (
+                format!(
+                    "{}:1\n{}:1\n",
+                    name(&dir.join("b.txt")),
+                    name(&dir.join("sub").join("a.txt"))
+                ),
+                1
+            )
+        );
+    }
+
+    /// Gives out its bytes, then fails.
+    struct Failing<'a>(&'a [u8]);
+
+    impl io::Read for Failing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("disk on fire"));
+            }
+            let n = self.0.len().min(buf.len()).min(4);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let grep = |args: &[&str], input| grep_with(args, Failing(input));
+        let input = b"ok\r\ncaf\xe9 error\nerror 2\nok\nerror 3\n";
+
+        // Stops reading before the failure.
+        assert_eq!(
+            grep(&["-l", "error"], input),
+            ("(standard input)\n".to_string(), 0)
+        );
+        assert_eq!(
+            grep(&["-m1", "error"], input),
+            ("caf\u{FFFD} error\n".to_string(), 0)
+        );
+        assert_eq!(
+            grep(&["-nb", "-m2", "-A1", "error"], input),
+            (
+                "2:4:caf\u{FFFD} error\n3:15:error 2\n4-23-ok\n".to_string(),
+                0
+            )
+        );
+        // Reads to the end, reports the failure, and keeps what it found.
+        assert_eq!(grep(&["-c", "error"], input), ("3\n".to_string(), 1));
+
+        // A `\r` and an `é` split between chunks of 4 bytes.
+        let split = "ok\r\ncaf\r é\r\nabé\n".as_bytes();
+        assert_eq!(
+            grep(&["-n", "-m1", "-e", "\r é$"], split),
+            ("2:caf\r é\n".to_string(), 0)
+        );
+        assert_eq!(grep(&["-n", "bé"], split), ("3:abé\n".to_string(), 1));
+    }
+
+    #[test]
+    fn long_lines() {
+        let mut input = vec![b'x'; MAX_LINE + 100];
+        input.extend_from_slice(" Érror\nerror 2\n".as_bytes());
+        let grep = |args: &[&str]| grep_with(args, input.as_slice());
+
+        // Matches past the cut are found, whatever the pattern.
+        for (args, count) in [
+            (&["-c", "rror"][..], "2\n"),
+            (&["-cw", "Érror"], "1\n"),
+            (&["-cw", "rror"], "0\n"),
+            (&["-ci", "ÉRROR"], "1\n"),
+            (&["-ciw", "érror"], "1\n"),
+            (&["-c", "-e", "[eÉ]rr?or$"], "1\n"),
+            (&["-cw", "-e", "\\w+or"], "2\n"),
+        ] {
+            assert_eq!(grep(args), (count.to_string(), 0), "{:?}", args);
+        }
+
+        // Only what is printed is cut, and the offsets count the whole line.
+        let (out, errors) = grep(&["-nb", "rror"]);
+        assert_eq!(errors, 0);
+        let (first, second) = out.split_once('\n').unwrap();
+        assert_eq!(first.len(), "1:0:".len() + MAX_LINE);
+        assert!(first[4..].bytes().all(|b| b == b'x'));
+        assert_eq!(second, format!("2:{}:error 2\n", MAX_LINE + 108));
+
+        // A pattern too large to be matched in pieces only searches what is
+        // kept, and says so.
+        assert_eq!(grep(&["-c", "-e", "a.{16}"]), ("0\n".to_string(), 1));
     }
 }
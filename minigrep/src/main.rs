@@ -1,22 +1,33 @@
-use minigrep::run;
-use minigrep::Config;
+use minigrep::{run, Command, HELP, USAGE};
 use std::env;
 use std::process;
 
 fn main() {
-    /* Accepting Command Line Arguments */
-    // let args: Vec<String> = env::args().collect();
-    // println!("{:?}", args);
+    let config = match Command::parse(env::args()) {
+        Ok(Command::Search(config)) => config,
+        Ok(Command::Help) => {
+            println!("{}\n{}", USAGE, HELP);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("minigrep {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("minigrep: {}", err);
+            eprintln!("{}", USAGE);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(2);
+        }
+    };
 
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Probelm parsing argumrnts: {}", err);
-        process::exit(1);
-    });
-    // println!("Search for {}", config.query);
-    // println!("In file {}", config.filename);
-
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    match run(config) {
+        Ok(summary) if summary.errors > 0 => process::exit(2),
+        Ok(summary) if summary.selected => {}
+        Ok(_) => process::exit(1),
+        Err(e) => {
+            eprintln!("minigrep: {}", e);
+            process::exit(2);
+        }
     }
 }
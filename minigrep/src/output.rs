@@ -0,0 +1,137 @@
+use crate::config::Config;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Capacity a kept line's buffer is shrunk back to once a shorter line
+/// reuses it.
+const LINE_CAPACITY: usize = 4 * 1024;
+
+/// A line kept back as possible context before a selected line.
+struct Line {
+    number: usize,
+    offset: usize,
+    text: String,
+}
+
+/// Writes selected lines and their context the way grep does: selected
+/// lines have `:` after their prefixes and context lines `-`, and groups of
+/// lines which are not next to each other are separated by `--`.
+///
+/// Only the last `before_context` lines are kept, so memory does not grow
+/// with the input.
+pub struct Printer<'c, W> {
+    config: &'c Config,
+    out: W,
+    before: VecDeque<Line>,
+    /// Context lines still to print after the last selected line.
+    after: usize,
+    /// Number of the last line printed from the current file.
+    last: Option<usize>,
+    /// Whether anything was printed yet, from any file.
+    printed: bool,
+}
+
+impl<'c, W: Write> Printer<'c, W> {
+    pub fn new(config: &'c Config, out: W) -> Self {
+        Self {
+            config,
+            out,
+            before: VecDeque::with_capacity(config.before_context),
+            after: 0,
+            last: None,
+            printed: false,
+        }
+    }
+
+    /// Forgets the context of the previous file.
+    pub fn start(&mut self) {
+        self.before.clear();
+        self.after = 0;
+        self.last = None;
+    }
+
+    /// Takes the next line of the file `name`, printing it if it is selected
+    /// or in the context of a selected line.
+    pub fn line(
+        &mut self,
+        name: &str,
+        number: usize,
+        offset: usize,
+        text: &str,
+        selected: bool,
+    ) -> io::Result<()> {
+        if selected {
+            while let Some(line) = self.before.pop_front() {
+                self.write(name, line.number, line.offset, &line.text, '-')?;
+            }
+            self.after = self.config.after_context;
+            return self.write(name, number, offset, text, ':');
+        }
+        if self.after > 0 {
+            self.after -= 1;
+            return self.write(name, number, offset, text, '-');
+        }
+        if self.config.before_context > 0 {
+            // Reuse the oldest line's buffer once the window is full.
+            let mut line = if self.before.len() == self.config.before_context {
+                self.before.pop_front().unwrap()
+            } else {
+                Line {
+                    number: 0,
+                    offset: 0,
+                    text: String::new(),
+                }
+            };
+            line.number = number;
+            line.offset = offset;
+            line.text.clear();
+            line.text.shrink_to(text.len().max(LINE_CAPACITY));
+            line.text.push_str(text);
+            self.before.push_back(line);
+        }
+        Ok(())
+    }
+
+    /// Whether lines of context after a selected line are still to come.
+    pub fn wants_context(&self) -> bool {
+        self.after > 0
+    }
+
+    pub fn count(&mut self, name: &str, count: usize) -> io::Result<()> {
+        if self.config.with_filename {
+            write!(self.out, "{}:", name)?;
+        }
+        writeln!(self.out, "{}", count)
+    }
+
+    pub fn file_name(&mut self, name: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", name)
+    }
+
+    fn write(
+        &mut self,
+        name: &str,
+        number: usize,
+        offset: usize,
+        text: &str,
+        sep: char,
+    ) -> io::Result<()> {
+        let context = self.config.before_context > 0 || self.config.after_context > 0;
+        if context && self.printed && self.last.is_none_or(|last| last + 1 != number) {
+            writeln!(self.out, "--")?;
+        }
+        self.printed = true;
+        self.last = Some(number);
+
+        if self.config.with_filename {
+            write!(self.out, "{}{}", name, sep)?;
+        }
+        if self.config.line_number {
+            write!(self.out, "{}{}", number, sep)?;
+        }
+        if self.config.byte_offset {
+            write!(self.out, "{}{}", offset, sep)?;
+        }
+        writeln!(self.out, "{}", text)
+    }
+}
@@ -0,0 +1,390 @@
+use regexp::{Fsm, Matcher};
+use std::ops::Range;
+
+/// What lines are searched for.
+pub enum Pattern {
+    Fixed(String),
+    /// A fixed string matched regardless of case, stored case folded.
+    IgnoreCase(Vec<char>),
+    /// A regular expression wrapped by `line_regex`, so that it matches the
+    /// lines which contain a match, and whole words only if that was asked
+    /// for when wrapping.
+    Regex(Box<Fsm>),
+    /// A regular expression whose wrapped automaton would be too large.
+    /// Only the part of a line kept for printing is searched for it.
+    Search(Box<Fsm>),
+    /// Several patterns, of which a line needs to match any.
+    Any(Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn ignore_case(query: &str) -> Self {
+        Pattern::IgnoreCase(query.chars().map(fold).collect())
+    }
+
+    /// Whether `line` contains a match. With `word`, only a match which
+    /// neither starts nor ends inside a word counts.
+    pub fn is_match(&self, line: &str, word: bool) -> bool {
+        let mut matcher = self.matcher(word);
+        matcher.feed(line);
+        matcher.finish(line)
+    }
+
+    /// Starts matching a line given in pieces.
+    pub fn matcher(&self, word: bool) -> LineMatcher<'_> {
+        let state = match self {
+            Pattern::Fixed(query) => State::Window {
+                text: String::new(),
+                line_start: true,
+                chars: query.chars().count(),
+            },
+            Pattern::IgnoreCase(query) => State::Window {
+                text: String::new(),
+                line_start: true,
+                chars: query.len(),
+            },
+            Pattern::Regex(fsm) => State::Regex(fsm.matcher()),
+            Pattern::Search(_) => State::Search,
+            Pattern::Any(patterns) => {
+                State::Any(patterns.iter().map(|p| p.matcher(word)).collect())
+            }
+        };
+        LineMatcher {
+            pattern: self,
+            word,
+            matched: false,
+            state,
+        }
+    }
+
+    /// Whether a line is matched whatever its length, and not only the part
+    /// of it which is kept.
+    pub fn is_streamed(&self) -> bool {
+        match self {
+            Pattern::Search(_) => false,
+            Pattern::Any(patterns) => patterns.iter().all(Pattern::is_streamed),
+            _ => true,
+        }
+    }
+
+    fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        match self {
+            Pattern::Fixed(query) => find_str(text, query, start),
+            Pattern::IgnoreCase(query) => find_folded(text, query, start),
+            Pattern::Regex(fsm) | Pattern::Search(fsm) => fsm.find_at(text, start),
+            Pattern::Any(patterns) => patterns
+                .iter()
+                .filter_map(|p| p.find_at(text, start))
+                .min_by_key(|m| m.start),
+        }
+    }
+}
+
+/// Matches a line given in pieces. Only what a match may still need is
+/// kept, so memory does not depend on the length of the line.
+pub struct LineMatcher<'p> {
+    pattern: &'p Pattern,
+    word: bool,
+    matched: bool,
+    state: State<'p>,
+}
+
+enum State<'p> {
+    Regex(Matcher<'p>),
+    /// The end of the line read so far, long enough to hold a match of a
+    /// fixed string of `chars` characters with the character before it.
+    Window {
+        text: String,
+        /// Whether `text` starts the line.
+        line_start: bool,
+        chars: usize,
+    },
+    Search,
+    Any(Vec<LineMatcher<'p>>),
+}
+
+impl LineMatcher<'_> {
+    /// Starts over with the next line.
+    pub fn reset(&mut self) {
+        self.matched = false;
+        match &mut self.state {
+            State::Regex(matcher) => matcher.reset(),
+            State::Window {
+                text, line_start, ..
+            } => {
+                text.clear();
+                *line_start = true;
+            }
+            State::Search => {}
+            State::Any(matchers) => matchers.iter_mut().for_each(LineMatcher::reset),
+        }
+    }
+
+    /// Takes the next piece of the line.
+    pub fn feed(&mut self, piece: &str) {
+        if self.matched {
+            return;
+        }
+        match &mut self.state {
+            State::Regex(matcher) => self.matched = matcher.feed(piece.as_bytes()) == Some(true),
+            State::Window {
+                text,
+                line_start,
+                chars,
+            } => {
+                text.push_str(piece);
+                self.matched = any_match(text, self.word, (*line_start, false), |start| {
+                    self.pattern.find_at(text, start)
+                });
+                if let Some((i, _)) = text.char_indices().rev().nth(*chars) {
+                    if i > 0 {
+                        text.drain(..i);
+                        *line_start = false;
+                    }
+                }
+            }
+            State::Search => {}
+            State::Any(matchers) => {
+                for matcher in matchers.iter_mut() {
+                    matcher.feed(piece);
+                }
+                self.matched = matchers.iter().any(|matcher| matcher.matched);
+            }
+        }
+    }
+
+    /// Ends the line and tells whether it matched. A pattern which is not
+    /// streamed is searched for in `kept`, what was kept of the line.
+    pub fn finish(&mut self, kept: &str) -> bool {
+        if self.matched {
+            return true;
+        }
+        match &mut self.state {
+            State::Regex(matcher) => matcher.finish(),
+            State::Window {
+                text, line_start, ..
+            } => any_match(text, self.word, (*line_start, true), |start| {
+                self.pattern.find_at(text, start)
+            }),
+            State::Search => any_match(kept, self.word, (true, true), |start| {
+                self.pattern.find_at(kept, start)
+            }),
+            State::Any(matchers) => matchers.iter_mut().any(|matcher| matcher.finish(kept)),
+        }
+    }
+}
+
+/// Wraps a regular expression into one which matches the lines containing
+/// a match of it. With `word`, the match may neither start nor end inside a
+/// word, and every match of the expression is tried, not only the
+/// leftmost-first one, so `fo+?` finds the word `foo`.
+pub fn line_regex(query: &str, word: bool) -> String {
+    if word {
+        format!("(?s:.*\\W)?(?:{})(?s:\\W.*)?$", query)
+    } else {
+        format!("(?s:.*)(?:{})", query)
+    }
+}
+
+fn find_str(text: &str, query: &str, start: usize) -> Option<Range<usize>> {
+    let i = start + text[start..].find(query)?;
+    Some(i..i + query.len())
+}
+
+/// Simple case folding: the character every character of the same case
+/// class maps to, when it maps to a single one.
+pub fn fold(c: char) -> char {
+    match c {
+        // Lowercase letters whose fold is another lowercase letter.
+        'ς' => 'σ',
+        'ſ' => 's',
+        '\u{345}' => 'ι',
+        'ϐ' => 'β',
+        'ϑ' => 'θ',
+        'ϕ' => 'φ',
+        'ϖ' => 'π',
+        'ϰ' => 'κ',
+        'ϱ' => 'ρ',
+        'ϵ' => 'ε',
+        'ẛ' => 'ṡ',
+        _ if c.is_ascii() => c.to_ascii_lowercase(),
+        _ => {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) => l,
+                _ => c,
+            }
+        }
+    }
+}
+
+/// Leftmost match of the folded `query` in `text` at or after `start`,
+/// folding `text` one character at a time.
+pub fn find_folded(text: &str, query: &[char], start: usize) -> Option<Range<usize>> {
+    if query.is_empty() {
+        return Some(start..start);
+    }
+    let rest = &text[start..];
+    rest.char_indices().find_map(|(i, _)| {
+        let mut chars = rest[i..].chars();
+        let mut end = start + i;
+        for &q in query {
+            let c = chars.next().filter(|&c| fold(c) == q)?;
+            end += c.len_utf8();
+        }
+        Some(start + i..end)
+    })
+}
+
+/// Whether the pattern has an uppercase letter, which turns off smart
+/// case. In a regular expression, escapes such as `\S` and `\p{Lu}` do not
+/// count.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            if let Some('p' | 'P') = chars.next() {
+                if chars.clone().next() == Some('{') {
+                    chars.find(|&c| c == '}');
+                } else {
+                    chars.next();
+                }
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Tries `find` at every start position in `text` until it returns a match
+/// which is acceptable as a whole word, if `word` asks for one. Only one
+/// match is tried at each position, which is enough for fixed strings; a
+/// regular expression needs `line_regex` for that.
+///
+/// `text` may be a part of the line, and `bounds` tell whether it starts and
+/// ends it. A match at its start is only taken at the start of the line, as
+/// any other was seen before, and one at its end, with `word`, only at the
+/// end of the line, as the character after it is still to come.
+fn any_match(
+    text: &str,
+    word: bool,
+    bounds: (bool, bool),
+    find: impl Fn(usize) -> Option<Range<usize>>,
+) -> bool {
+    let (line_start, line_end) = bounds;
+    let mut start = 0;
+    while let Some(m) = find(start) {
+        let known = (line_start || m.start > 0) && (line_end || !word || m.end < text.len());
+        if known && (!word || is_word_bounded(text, &m)) {
+            return true;
+        }
+        match text[m.start..].chars().next() {
+            Some(c) => start = m.start + c.len_utf8(),
+            None => return false,
+        }
+    }
+    false
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_word_bounded(text: &str, m: &Range<usize>) -> bool {
+    !text[..m.start]
+        .chars()
+        .next_back()
+        .is_some_and(is_word_char)
+        && !text[m.end..].chars().next().is_some_and(is_word_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_words() {
+        let fixed = Pattern::Fixed("body".to_string());
+        assert!(fixed.is_match("I'm nobody!", false));
+        assert!(!fixed.is_match("I'm nobody!", true));
+        assert!(fixed.is_match("body and nobody", true));
+        assert!(fixed.is_match("nobody, body", true));
+
+        let regex =
+            |query, word| Pattern::Regex(Box::new(Fsm::compile(&line_regex(query, word)).unwrap()));
+        assert!(regex("no\\w*", false).is_match("Snobs", false));
+        assert!(!regex("no\\w*", true).is_match("Snobs", true));
+        assert!(regex("no\\w*", true).is_match("Snobs say no", true));
+        assert!(regex("fo+?", true).is_match("foo", true));
+        assert!(regex("fo+?", true).is_match("a fooo, b", true));
+        assert!(!regex("fo+?", true).is_match("food", true));
+        assert!(regex("^foo", true).is_match("foo bar", true));
+        assert!(!regex("^foo", true).is_match("bar foo", true));
+        let search = Pattern::Search(Box::new(Fsm::compile("no\\w*").unwrap()));
+        assert!(search.is_match("Snobs say no", true));
+        assert!(!search.is_match("Snobs", true));
+
+        let ignore_case = Pattern::ignore_case("are");
+        assert!(ignore_case.is_match("Are you nobody, too?", true));
+        assert!(!ignore_case.is_match("How dreary", true));
+    }
+
+    #[test]
+    fn regex() {
+        let regex = Pattern::Regex(Box::new(
+            Fsm::compile(&line_regex("[A-Z][a-z]+ t", false)).unwrap(),
+        ));
+        assert!(!regex.is_match("safe, fast, productive.", false));
+        assert!(regex.is_match("Pick three.", false));
+        assert!(regex.is_match("Duct tape", false));
+    }
+
+    #[test]
+    fn pieces() {
+        let matches = |pattern: &Pattern, word, pieces: &[&str]| {
+            let mut matcher = pattern.matcher(word);
+            for piece in pieces {
+                matcher.feed(piece);
+            }
+            matcher.finish("")
+        };
+        let fixed = Pattern::Fixed("body".to_string());
+        assert!(matches(&fixed, false, &["I'm no", "bo", "dy!"]));
+        assert!(!matches(&fixed, true, &["I'm no", "bo", "dy!"]));
+        assert!(matches(&fixed, true, &["somebody, ", "", "bo", "dy"]));
+        assert!(!matches(&fixed, true, &["some", "body", "guard"]));
+        assert!(matches(&fixed, true, &["some body", " guard"]));
+        let ignore_case = Pattern::ignore_case("ΣΟΦΟΣ");
+        assert!(matches(&ignore_case, true, &["είναι σο", "φός, σοφ", "ος"]));
+
+        let mut matcher = fixed.matcher(true);
+        matcher.feed("body");
+        assert!(matcher.finish(""));
+        matcher.reset();
+        matcher.feed("nobody");
+        assert!(!matcher.finish(""));
+
+        let any = Pattern::Any(vec![fixed, Pattern::ignore_case("frog")]);
+        assert!(matches(&any, true, &["how public, like a Fr", "og"]));
+        assert!(matches(&any, true, &["a ", "body", "!"]));
+        assert!(!matches(&any, true, &["nobody", " froggy"]));
+    }
+
+    #[test]
+    fn case_folding() {
+        let query = "ΣΟΦΟΣ".chars().map(fold).collect::<Vec<_>>();
+        assert_eq!(find_folded("ὁ σοφός", &query, 0), None);
+        assert_eq!(find_folded("είναι σοφοσ, σοφος", &query, 0), Some(11..21));
+        assert_eq!(find_folded("ein σοφος", &query, 4), Some(4..14));
+        let query = "STRASSE".chars().map(fold).collect::<Vec<_>>();
+        assert_eq!(find_folded("Hauptſtraſſe", &query, 0), Some(5..15));
+        assert_eq!(find_folded("abc", &[], 1), Some(1..1));
+
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase("rust", false));
+        assert!(has_uppercase("\\Rust", false));
+        assert!(!has_uppercase("\\S+\\p{Uppercase} \\P{Lu}\\pL", true));
+        assert!(has_uppercase("\\w+ Rust", true));
+    }
+}
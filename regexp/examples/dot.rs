@@ -0,0 +1,33 @@
+extern crate regexp;
+use regexp::Fsm;
+use std::env;
+use std::io::{self, BufRead};
+use std::process;
+
+fn main() {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let unminimized = args.iter().any(|arg| arg == "--unminimized");
+    args.retain(|arg| arg != "--unminimized");
+
+    let pattern = match args.pop() {
+        Some(pattern) => pattern,
+        None => {
+            let mut line = String::new();
+            let _ = io::stdin().lock().read_line(&mut line);
+            line.trim_end_matches(['\n', '\r']).to_string()
+        }
+    };
+
+    let fsm = if unminimized {
+        Fsm::compile_unminimized(&pattern)
+    } else {
+        Fsm::compile(&pattern)
+    };
+    match fsm {
+        Ok(fsm) => print!("{}", fsm.to_dot()),
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -1,22 +1,100 @@
 extern crate regexp;
-use regexp::turnstile::{next_state, state_to_str, Event, State};
-use std::io::{self, BufRead, Write};
+use regexp::machine::StateMachine;
+use regexp::record::{replay, Record};
+use regexp::turnstile::{coin_counter, Counter, Event, Output, Turnstile};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("Usage: turnstile [--record FILE | --replay FILE]");
+    process::exit(2);
+}
 
 fn main() {
-    let mut state = State::Locked;
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let mut log = None;
+    match args.as_slice() {
+        [] => {}
+        [flag, path] if flag == "--record" => {
+            let file = File::create(path).unwrap_or_else(|err| {
+                eprintln!("ERROR: could not create {}: {}", path, err);
+                process::exit(1);
+            });
+            log = Some(file);
+        }
+        [flag, path] if flag == "--replay" => {
+            // Replays on the same machine, so that the coin box counts.
+            let mut turnstile = coin_counter(Counter::default());
+            let result = File::open(path).and_then(|file| {
+                replay(
+                    BufReader::new(file),
+                    |_, event| {
+                        let _ = turnstile.handle(event);
+                        turnstile.state()
+                    },
+                    Turnstile::STATES,
+                    Turnstile::EVENTS,
+                )
+            });
+            match result {
+                Ok(None) => println!("{}: replayed without divergence", path),
+                Ok(Some(divergence)) => {
+                    println!("{}: diverged at {}", path, divergence);
+                    process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("ERROR: could not replay {}: {}", path, err);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => usage(),
+    }
+
+    let mut turnstile = coin_counter(Counter::default());
 
-    println!("State: {}", state_to_str(&state));
+    println!("State: {}", turnstile.state());
     print!("> ");
     let _ = io::stdout().flush();
     for line in io::stdin().lock().lines() {
-        match line.unwrap().as_str() {
-            "coin" => state = next_state(state, Event::Coin),
-            "push" => state = next_state(state, Event::Push),
+        let event = match line.unwrap().as_str() {
+            "coin" => Some(Event::Coin),
+            "push" => Some(Event::Push),
             "quit" | "q" => break,
-            unknown => eprintln!("ERROR: Unknown event {}", unknown),
+            unknown => {
+                eprintln!("ERROR: Unknown event {}", unknown);
+                None
+            }
+        };
+
+        if let Some(event) = event {
+            let from = turnstile.state();
+            let handled = turnstile.handle(event);
+            if let (Some(log), Ok(_)) = (log.as_mut(), &handled) {
+                let record = Record::now(from, event, turnstile.state());
+                if let Err(err) = writeln!(log, "{}", record).and_then(|()| log.flush()) {
+                    eprintln!("ERROR: could not record {}: {}", event, err);
+                }
+            }
+            match handled {
+                Ok(Some(Output::Alarm)) => println!("Push rejected: the turnstile is locked"),
+                Ok(Some(Output::Refunded)) => println!("Coin refunded: already unlocked"),
+                Ok(Some(Output::Full)) => println!("Coin refunded: the coin box is full"),
+                Ok(Some(Output::Accepted)) | Ok(None) => {}
+                Err(err) => eprintln!("ERROR: {}", err),
+            }
         }
 
-        println!("State: {}", state_to_str(&state));
+        let counter = turnstile.context();
+        println!(
+            "State: {} (coins: {}, rejected pushes: {})",
+            turnstile.state(),
+            counter.coins,
+            counter.rejected_pushes
+        );
         print!("> ");
         let _ = io::stdout().flush();
     }
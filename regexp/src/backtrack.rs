@@ -0,0 +1,514 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use crate::captures::Captures;
+use crate::class::CharClass;
+use crate::nfa::Context;
+use crate::parse::{Ast, Look};
+use crate::{CompileError, RegexBuilder};
+
+/// Steps a `Backtracker` may take per search unless told otherwise.
+pub const DEFAULT_STEP_BUDGET: usize = 1_000_000;
+
+/// Largest number of operations a pattern may compile into.
+const MAX_OPS: usize = 100_000;
+
+/// A search took more steps than its budget allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout {
+    pub steps: usize,
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "backtracking gave up after {} steps", self.steps)
+    }
+}
+
+impl Error for Timeout {}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Class(CharClass),
+    Look(Look),
+    /// Tries the first pc, and the second one if that fails.
+    Split(usize, usize),
+    Jump(usize),
+    /// Records the current position in a slot.
+    Save(usize),
+    /// Fails unless input was consumed since the position saved in the
+    /// slot, so that a loop whose body can match nothing ends.
+    Progress(usize),
+    BackRef {
+        index: usize,
+        case_insensitive: bool,
+    },
+    /// Runs the program at `body` on its own and goes on if it matched, or
+    /// if it did not when `negate` is set.
+    LookAround {
+        ahead: bool,
+        negate: bool,
+        body: usize,
+    },
+    /// End of the pattern or of a look-around.
+    Done,
+}
+
+enum Frame {
+    Try(usize, usize),
+    Restore(usize, Option<usize>),
+}
+
+/// Matches patterns no finite automaton can: backreferences `\1` to `\9`,
+/// lookahead `(?=...)` / `(?!...)` and lookbehind `(?<=...)` / `(?<!...)`,
+/// by trying the alternatives of the pattern one after the other. That may
+/// take exponential time, so every search has a budget of steps and fails
+/// with `Timeout` once it is spent.
+///
+/// ```
+/// use regexp::Backtracker;
+///
+/// let re = Backtracker::new("(\\w+) \\1").unwrap();
+/// assert_eq!(re.find("it is is it").unwrap(), Some(3..8));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Backtracker {
+    ops: Vec<Op>,
+    names: Vec<Option<String>>,
+    /// Capture slots followed by the slots of `Progress`.
+    slots: usize,
+    budget: usize,
+}
+
+impl Backtracker {
+    /// Compiles `src` with the default options and step budget. See
+    /// `RegexBuilder::build_backtracker` to change them.
+    pub fn new(src: &str) -> Result<Self, CompileError> {
+        RegexBuilder::new(src).build_backtracker()
+    }
+
+    pub(crate) fn from_ast(ast: &Ast, budget: usize) -> Result<Self, CompileError> {
+        let mut names = vec![None];
+        collect_names(ast, &mut names);
+        let mut re = Self {
+            ops: Vec::new(),
+            slots: names.len() * 2,
+            names,
+            budget,
+        };
+        re.push(Op::Save(0))?;
+        re.emit(ast)?;
+        re.push(Op::Save(1))?;
+        re.push(Op::Done)?;
+        Ok(re)
+    }
+
+    fn push(&mut self, op: Op) -> Result<usize, CompileError> {
+        if self.ops.len() >= MAX_OPS {
+            return Err(CompileError::new(0, "pattern is too large"));
+        }
+        self.ops.push(op);
+        Ok(self.ops.len() - 1)
+    }
+
+    /// Emits the operations of `ast`, which fall through to the next one
+    /// once it has matched.
+    fn emit(&mut self, ast: &Ast) -> Result<(), CompileError> {
+        match ast {
+            Ast::Empty => {}
+            Ast::Class(class) => {
+                self.push(Op::Class(class.clone()))?;
+            }
+            Ast::Look(look) => {
+                self.push(Op::Look(*look))?;
+            }
+            Ast::Concat(items) => {
+                for item in items.iter() {
+                    self.emit(item)?;
+                }
+            }
+            Ast::Group(ast) => self.emit(ast)?,
+            Ast::Alternate(alternatives) => {
+                let mut jumps = Vec::new();
+                for (i, ast) in alternatives.iter().enumerate() {
+                    let split = (i + 1 < alternatives.len())
+                        .then(|| self.push(Op::Done))
+                        .transpose()?;
+                    self.emit(ast)?;
+                    if let Some(split) = split {
+                        jumps.push(self.push(Op::Done)?);
+                        self.ops[split] = Op::Split(split + 1, self.ops.len());
+                    }
+                }
+                let end = self.ops.len();
+                for jump in jumps {
+                    self.ops[jump] = Op::Jump(end);
+                }
+            }
+            Ast::Capture { index, ast, .. } => {
+                self.push(Op::Save(index * 2))?;
+                self.emit(ast)?;
+                self.push(Op::Save(index * 2 + 1))?;
+            }
+            &Ast::BackRef {
+                index,
+                case_insensitive,
+                ..
+            } => {
+                self.push(Op::BackRef {
+                    index,
+                    case_insensitive,
+                })?;
+            }
+            Ast::LookAround {
+                ahead, negate, ast, ..
+            } => {
+                let look = self.push(Op::Done)?;
+                let jump = self.push(Op::Done)?;
+                let body = self.ops.len();
+                self.emit(ast)?;
+                self.push(Op::Done)?;
+                self.ops[look] = Op::LookAround {
+                    ahead: *ahead,
+                    negate: *negate,
+                    body,
+                };
+                self.ops[jump] = Op::Jump(self.ops.len());
+            }
+            Ast::Repeat {
+                ast,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.emit(ast)?;
+                }
+                let mut splits = Vec::new();
+                match max {
+                    Some(max) => {
+                        for _ in *min..*max {
+                            splits.push(self.push(Op::Done)?);
+                            self.emit(ast)?;
+                        }
+                    }
+                    None => {
+                        let slot = self.slots;
+                        self.slots += 1;
+                        let split = self.push(Op::Done)?;
+                        splits.push(split);
+                        self.push(Op::Save(slot))?;
+                        self.emit(ast)?;
+                        self.push(Op::Progress(slot))?;
+                        self.push(Op::Jump(split))?;
+                    }
+                }
+                let end = self.ops.len();
+                for split in splits {
+                    self.ops[split] = if *greedy {
+                        Op::Split(split + 1, end)
+                    } else {
+                        Op::Split(end, split + 1)
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the input starts with a match, like `Fsm::match_str`.
+    pub fn match_str(&self, input: &str) -> Result<bool, Timeout> {
+        let mut slots = vec![None; self.slots];
+        let mut steps = 0;
+        Ok(self
+            .run(input, 0, 0, None, &mut slots, &mut steps)?
+            .is_some())
+    }
+
+    /// Byte range of the leftmost match in `text`, picked like `Fsm::find`.
+    pub fn find(&self, text: &str) -> Result<Option<Range<usize>>, Timeout> {
+        Ok(self.captures(text)?.and_then(|caps| caps.range(0)))
+    }
+
+    /// The groups of the leftmost match in `text`.
+    pub fn captures<'a>(&'a self, text: &'a str) -> Result<Option<Captures<'a>>, Timeout> {
+        let mut slots = vec![None; self.slots];
+        let mut steps = 0;
+        let starts = text.char_indices().map(|(i, _)| i).chain([text.len()]);
+        for start in starts {
+            slots.fill(None);
+            if self
+                .run(text, 0, start, None, &mut slots, &mut steps)?
+                .is_some()
+            {
+                slots.truncate(self.names.len() * 2);
+                return Ok(Some(Captures::new(text, slots, &self.names)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs the program from `pc` at `pos` until a `Done` is reached, at
+    /// `until` if given, and returns the position there. `slots` keep what
+    /// the successful path saved.
+    fn run(
+        &self,
+        text: &str,
+        pc: usize,
+        pos: usize,
+        until: Option<usize>,
+        slots: &mut [Option<usize>],
+        steps: &mut usize,
+    ) -> Result<Option<usize>, Timeout> {
+        let mut stack = vec![Frame::Try(pc, pos)];
+        while let Some(frame) = stack.pop() {
+            let (mut pc, mut pos) = match frame {
+                Frame::Try(pc, pos) => (pc, pos),
+                Frame::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            loop {
+                *steps += 1;
+                if *steps > self.budget {
+                    return Err(Timeout { steps: self.budget });
+                }
+                match &self.ops[pc] {
+                    Op::Class(class) => match text[pos..].chars().next() {
+                        Some(c) if class.contains(c) => {
+                            pos += c.len_utf8();
+                            pc += 1;
+                        }
+                        _ => break,
+                    },
+                    Op::Look(look) => {
+                        if !Context::at(text, pos).holds(*look) {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    &Op::Split(first, second) => {
+                        stack.push(Frame::Try(second, pos));
+                        pc = first;
+                    }
+                    &Op::Jump(to) => pc = to,
+                    &Op::Save(slot) => {
+                        stack.push(Frame::Restore(slot, slots[slot]));
+                        slots[slot] = Some(pos);
+                        pc += 1;
+                    }
+                    &Op::Progress(slot) => {
+                        if slots[slot] == Some(pos) {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    &Op::BackRef {
+                        index,
+                        case_insensitive,
+                    } => {
+                        let (Some(start), Some(end)) = (slots[index * 2], slots[index * 2 + 1])
+                        else {
+                            break;
+                        };
+                        match starts_with(&text[pos..], &text[start..end], case_insensitive) {
+                            Some(len) => {
+                                pos += len;
+                                pc += 1;
+                            }
+                            None => break,
+                        }
+                    }
+                    &Op::LookAround {
+                        ahead,
+                        negate,
+                        body,
+                    } => {
+                        let mut inner = slots.to_vec();
+                        let matched = if ahead {
+                            self.run(text, body, pos, None, &mut inner, steps)?
+                                .is_some()
+                        } else {
+                            let mut matched = false;
+                            let starts = text[..pos].char_indices().map(|(i, _)| i);
+                            for start in [pos].into_iter().chain(starts.rev()) {
+                                inner.copy_from_slice(slots);
+                                if self
+                                    .run(text, body, start, Some(pos), &mut inner, steps)?
+                                    .is_some()
+                                {
+                                    matched = true;
+                                    break;
+                                }
+                            }
+                            matched
+                        };
+                        if matched == negate {
+                            break;
+                        }
+                        // Groups inside a positive look-around keep what
+                        // they captured.
+                        if !negate {
+                            for (slot, value) in inner.into_iter().enumerate() {
+                                if slots[slot] != value {
+                                    stack.push(Frame::Restore(slot, slots[slot]));
+                                    slots[slot] = value;
+                                }
+                            }
+                        }
+                        pc += 1;
+                    }
+                    Op::Done => {
+                        if until.is_none_or(|until| until == pos) {
+                            return Ok(Some(pos));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Length of the prefix of `text` equal to `prefix`, comparing case
+/// insensitively if asked.
+fn starts_with(text: &str, prefix: &str, case_insensitive: bool) -> Option<usize> {
+    if !case_insensitive {
+        return text.starts_with(prefix).then_some(prefix.len());
+    }
+    let mut len = 0;
+    let mut chars = text.chars();
+    for p in prefix.chars() {
+        let c = chars.next()?;
+        if c != p && !CharClass::single(p).case_fold().contains(c) {
+            return None;
+        }
+        len += c.len_utf8();
+    }
+    Some(len)
+}
+
+fn collect_names(ast: &Ast, names: &mut Vec<Option<String>>) {
+    match ast {
+        Ast::Concat(items) | Ast::Alternate(items) => {
+            for item in items.iter() {
+                collect_names(item, names);
+            }
+        }
+        Ast::Repeat { ast, .. } | Ast::Group(ast) | Ast::LookAround { ast, .. } => {
+            collect_names(ast, names)
+        }
+        Ast::Capture { index, name, ast } => {
+            if names.len() <= *index {
+                names.resize(index + 1, None);
+            }
+            names[*index] = name.clone();
+            collect_names(ast, names);
+        }
+        Ast::Empty | Ast::Class(_) | Ast::Look(_) | Ast::BackRef { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Fsm;
+
+    #[test]
+    fn backreferences() {
+        let re = Backtracker::new("(\\w+) \\1$").unwrap();
+        assert!(re.match_str("hello hello").unwrap());
+        assert!(!re.match_str("hello world").unwrap());
+        assert!(!re.match_str("hello hell").unwrap());
+
+        let re = Backtracker::new("(?<q>['\"]).*?\\1").unwrap();
+        assert_eq!(re.find("say 'it\"s' now").unwrap(), Some(4..10));
+
+        let re = Backtracker::new("(?i)(ab)\\1").unwrap();
+        assert!(re.match_str("abAB").unwrap());
+        // A group which did not take part in the match matches nothing.
+        let re = Backtracker::new("(a)?b\\1").unwrap();
+        assert!(!re.match_str("b").unwrap());
+    }
+
+    #[test]
+    fn look_around() {
+        let cases = [
+            ("foo(?=bar)", "foobaz foobar", Some(7..10)),
+            ("foo(?!bar)", "foobar foobaz", Some(7..10)),
+            ("(?<=\\$)\\d+", "cost: $42", Some(7..9)),
+            ("(?<!-)\\d+", "-5 7", Some(3..4)),
+            ("(?<=a+)b", "aaab", Some(3..4)),
+            ("(?<=^x)y", "xy", Some(1..2)),
+            ("(?<=^x)y", "xxy", None),
+            ("q(?=(u+))\\1", "quuux", Some(0..4)),
+        ];
+        for (pattern, text, expected) in cases {
+            let re = Backtracker::new(pattern).unwrap();
+            assert_eq!(re.find(text).unwrap(), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn agrees_with_fsm() {
+        let patterns = ["a+", "a*?b", "(ab)+c?", "x{2,3}", "(?m)^\\w+$", "é|"];
+        let texts = ["", "aab", "ababc", "xxxx", "one\ntwo", "zé|"];
+        for pattern in patterns {
+            let fsm = Fsm::compile(pattern).unwrap();
+            let re = Backtracker::new(pattern).unwrap();
+            for text in texts {
+                assert_eq!(re.find(text).unwrap(), fsm.find(text), "{}", pattern);
+                assert_eq!(re.match_str(text).unwrap(), fsm.match_str(text));
+                let fsm_caps = fsm.captures(text).map(|caps| caps.get(1));
+                let re_caps = re.captures(text).unwrap().map(|caps| caps.get(1));
+                assert_eq!(re_caps, fsm_caps, "{} in {:?}", pattern, text);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_loops_end() {
+        let re = Backtracker::new("(a*)*b").unwrap();
+        assert_eq!(re.find("aab").unwrap(), Some(0..3));
+        let re = Backtracker::new("(a?)*?c").unwrap();
+        assert_eq!(re.find("aac").unwrap(), Some(0..3));
+    }
+
+    #[test]
+    fn step_budget() {
+        let re = RegexBuilder::new("(a+)+b")
+            .step_budget(10_000)
+            .build_backtracker()
+            .unwrap();
+        let text = "a".repeat(30);
+        assert_eq!(re.find(&text), Err(Timeout { steps: 10_000 }));
+        assert_eq!(re.find("aaab").unwrap(), Some(0..4));
+        assert_eq!(
+            Timeout { steps: 10_000 }.to_string(),
+            "backtracking gave up after 10000 steps"
+        );
+    }
+
+    #[test]
+    fn fsm_rejects_extensions() {
+        assert_eq!(
+            Fsm::compile("(a)\\1").err().map(|e| (e.pos, e.msg)),
+            Some((3, "backreferences need the backtracking engine"))
+        );
+        assert_eq!(
+            Fsm::compile("a(?<!b)").err().map(|e| (e.pos, e.msg)),
+            Some((1, "look-around needs the backtracking engine"))
+        );
+        assert_eq!(
+            Fsm::compile("(?i)x(?=y)").err().map(|e| e.to_string()),
+            Some("look-around needs the backtracking engine at position 5".to_string())
+        );
+        assert_eq!(
+            Backtracker::new("(a)\\2").err().map(|e| (e.pos, e.msg)),
+            Some((3, "invalid backreference"))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use regexp::Fsm;
+use std::time::Instant;
+
+/// Bytes the original layout would take: 130 `(next, offset)` pairs of
+/// `usize` for every state.
+fn fixed_layout_size(fsm: &Fsm) -> usize {
+    fsm.state_count() * 130 * std::mem::size_of::<[usize; 2]>()
+}
+
+fn bench(f: impl Fn(&str) -> bool, inputs: &[String]) -> f64 {
+    let time = Instant::now();
+    for input in inputs.iter() {
+        std::hint::black_box(f(input));
+    }
+    time.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let inputs = (0..1000)
+        .map(|i| format!("{}{}", "abc123xyz".repeat(i % 50 + 1), "é中 😀"))
+        .collect::<Vec<_>>();
+
+    println!("pattern states classes fixed columns table columns-time table-time");
+    for n in [1, 10, 50, 100, 200] {
+        for (name, piece) in [
+            ("literal", "abc123xyz"),
+            ("classes", "[a-z]+\\d"),
+            ("unicode", "\\p{Alphabetic}+\\s"),
+        ] {
+            let fsm = Fsm::compile(&piece.repeat(n)).unwrap();
+            let usage = fsm.memory_usage();
+            let columns = bench(|s| fsm.match_columns(s), &inputs);
+            let table = bench(|s| fsm.match_str(s), &inputs);
+            println!(
+                "{}-{} {} {} {} {} {} {} {}",
+                name,
+                n,
+                fsm.state_count(),
+                fsm.class_count(),
+                fixed_layout_size(&fsm),
+                usage.columns,
+                usage.table,
+                columns,
+                table
+            );
+        }
+    }
+}
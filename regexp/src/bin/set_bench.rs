@@ -0,0 +1,62 @@
+use regexp::{Fsm, RegexSet};
+use std::time::Instant;
+
+const LEVELS: [&str; 4] = ["INFO", "WARN", "ERROR", "DEBUG"];
+const SOURCES: [&str; 5] = ["disk", "net", "auth", "db", "cache"];
+
+/// Patterns the way a log classifier would have them: a timestamp, a level
+/// and a source with a numbered event.
+fn patterns(n: usize) -> Vec<String> {
+    (0..n)
+        .map(|i| {
+            format!(
+                "\\d{{4}}-\\d\\d-\\d\\d {} {}{:03}:",
+                LEVELS[i % LEVELS.len()],
+                SOURCES[i % SOURCES.len()],
+                i
+            )
+        })
+        .collect()
+}
+
+fn bench(f: impl Fn(&str) -> usize, inputs: &[String]) -> (usize, f64) {
+    let time = Instant::now();
+    let mut total = 0;
+    for input in inputs.iter() {
+        total += std::hint::black_box(f(input));
+    }
+    (total, time.elapsed().as_secs_f64())
+}
+
+fn main() {
+    let inputs = (0..10_000)
+        .map(|i| {
+            format!(
+                "2024-05-{:02} {} {}{:03}: request took {}ms",
+                i % 28 + 1,
+                LEVELS[i % 3],
+                SOURCES[i % 7 % SOURCES.len()],
+                i % 250,
+                i
+            )
+        })
+        .collect::<Vec<_>>();
+
+    println!("patterns states set-time loop-time");
+    for n in [10, 50, 100, 200] {
+        let patterns = patterns(n);
+        let set = RegexSet::new(&patterns).unwrap();
+        let fsms = patterns
+            .iter()
+            .map(|p| Fsm::compile(p).unwrap())
+            .collect::<Vec<_>>();
+
+        let (set_total, set_time) = bench(|s| set.matches(s).len(), &inputs);
+        let (loop_total, loop_time) = bench(
+            |s| fsms.iter().filter(|fsm| fsm.match_str(s)).count(),
+            &inputs,
+        );
+        assert_eq!(set_total, loop_total);
+        println!("{} {} {} {}", n, set.state_count(), set_time, loop_time);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::backtrack::{Backtracker, DEFAULT_STEP_BUDGET};
+use crate::nfa::Nfa;
+use crate::parse::{self, Flags};
+use crate::{CompileError, Fsm};
+
+/// Compiles a pattern with options. Each option can also be switched on or
+/// off inside the pattern with an inline flag: `i`, `m` and `s` in `(?ims)`
+/// or `(?ims:...)`, turned off after a `-`.
+///
+/// ```
+/// use regexp::RegexBuilder;
+///
+/// let fsm = RegexBuilder::new("rust").case_insensitive(true).build().unwrap();
+/// assert!(fsm.match_str("RuSt"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    flags: Flags,
+    step_budget: usize,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            flags: Flags::default(),
+            step_budget: DEFAULT_STEP_BUDGET,
+        }
+    }
+
+    /// Letters match both their upper and lower case forms (`i`).
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    /// `^` and `$` match at the start and end of every line (`m`).
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.multi_line = yes;
+        self
+    }
+
+    /// `.` matches `\n` too (`s`).
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Steps a `Backtracker` may take in one search before giving up with
+    /// a `Timeout`.
+    pub fn step_budget(&mut self, steps: usize) -> &mut Self {
+        self.step_budget = steps;
+        self
+    }
+
+    pub fn build(&self) -> Result<Fsm, CompileError> {
+        let mut fsm = Fsm::from_nfa(Nfa::new(&parse::parse(&self.pattern, self.flags)?)?)?;
+        fsm.minimize();
+        Ok(fsm)
+    }
+
+    /// Compiles the pattern for the backtracking engine, which also accepts
+    /// backreferences and look-around.
+    pub fn build_backtracker(&self) -> Result<Backtracker, CompileError> {
+        Backtracker::from_ast(&parse::parse(&self.pattern, self.flags)?, self.step_budget)
+    }
+}
@@ -0,0 +1,330 @@
+use std::ops::Range;
+
+use crate::pikevm::Slots;
+use crate::Fsm;
+
+/// What the groups of a pattern matched in a text. Group `0` is the whole
+/// match.
+#[derive(Debug, Clone)]
+pub struct Captures<'a> {
+    text: &'a str,
+    slots: Slots,
+    names: &'a [Option<String>],
+}
+
+impl<'a> Captures<'a> {
+    pub(crate) fn new(text: &'a str, slots: Slots, names: &'a [Option<String>]) -> Self {
+        Self { text, slots, names }
+    }
+
+    /// Byte range matched by group `i`, if it took part in the match.
+    pub fn range(&self, i: usize) -> Option<Range<usize>> {
+        match (self.slots.get(i * 2)?, self.slots.get(i * 2 + 1)?) {
+            (Some(start), Some(end)) => Some(*start..*end),
+            _ => None,
+        }
+    }
+
+    /// Text matched by group `i`, if it took part in the match.
+    pub fn get(&self, i: usize) -> Option<&'a str> {
+        self.range(i).map(|range| &self.text[range])
+    }
+
+    /// Text matched by the group called `name`.
+    pub fn name(&self, name: &str) -> Option<&'a str> {
+        let i = self
+            .names
+            .iter()
+            .position(|other| other.as_deref() == Some(name))?;
+        self.get(i)
+    }
+
+    /// Number of groups, including group `0`.
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `replacement` to `dst` with `$1` or `${1}` replaced by the
+    /// text of group 1, `$name` or `${name}` by the text of the group called
+    /// `name`, and `$$` by `$`. A `$name` takes as many letters, digits and
+    /// `_` as follow it. Groups which did not match are replaced by nothing.
+    pub fn expand(&self, replacement: &str, dst: &mut String) {
+        let mut rest = replacement;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+            let (name, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+            if name.is_empty() {
+                dst.push('$');
+                continue;
+            }
+            let text = match name.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(name),
+            };
+            dst.push_str(text.unwrap_or(""));
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+impl Fsm {
+    /// Byte range of the leftmost match in `text`. Where several matches
+    /// start there, the one Perl would pick wins: quantifiers are greedy and
+    /// earlier parts of the pattern are preferred.
+    ///
+    /// Automata built out of columns only know where matches end once they
+    /// are sure, so for them it is the shortest match starting there, and
+    /// `^` holds wherever a match is tried.
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_at(text, 0)
+    }
+
+    /// Like `find`, starting the search at byte offset `start`. Assertions
+    /// still see the text before `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        let slots = self.search(text, start)?;
+        Some(slots[0]?..slots[1]?)
+    }
+
+    /// Every match in `text` which does not overlap the ones before it. An
+    /// empty match right after the previous match is skipped.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> Matches<'a> {
+        Matches {
+            fsm: self,
+            text,
+            next: 0,
+            last_end: None,
+        }
+    }
+
+    /// The groups of the leftmost match in `text`.
+    pub fn captures<'a>(&'a self, text: &'a str) -> Option<Captures<'a>> {
+        self.captures_at(text, 0)
+    }
+
+    pub(crate) fn captures_at<'a>(&'a self, text: &'a str, start: usize) -> Option<Captures<'a>> {
+        Some(Captures {
+            text,
+            slots: self.search(text, start)?,
+            names: self.nfa.as_ref().map_or(&[None][..], |nfa| &nfa.names),
+        })
+    }
+
+    fn search(&self, text: &str, start: usize) -> Option<Slots> {
+        match &self.nfa {
+            Some(nfa) => nfa.search(text, start),
+            None => self.search_table(text, start),
+        }
+    }
+
+    /// Runs the table from every position in turn, returning the first
+    /// span it accepts.
+    fn search_table(&self, text: &str, start: usize) -> Option<Slots> {
+        let table = &self.table;
+        let starts = text[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain([text.len()]);
+        for from in starts {
+            let mut state = 1;
+            let mut end = from;
+            for c in text[from..].chars() {
+                if state == 0 || state == table.accept {
+                    break;
+                }
+                state = table.next(state, c);
+                end += c.len_utf8();
+            }
+            if state == table.accept || (end == text.len() && table.eoi[state]) {
+                return Some(vec![Some(from), Some(end)]);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the matches of `Fsm::find_iter`.
+pub struct Matches<'a> {
+    fsm: &'a Fsm,
+    text: &'a str,
+    next: usize,
+    last_end: Option<usize>,
+}
+
+impl<'a> Matches<'a> {
+    /// Next match with its groups, shared by the iterators built on top.
+    pub(crate) fn next_captures(&mut self) -> Option<Captures<'a>> {
+        loop {
+            if self.next > self.text.len() {
+                return None;
+            }
+            let caps = self.fsm.captures_at(self.text, self.next)?;
+            let range = caps.range(0)?;
+            if range.is_empty() {
+                self.next = range.end
+                    + self.text[range.end..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+                if self.last_end == Some(range.end) {
+                    continue;
+                }
+            } else {
+                self.next = range.end;
+            }
+            self.last_end = Some(range.end);
+            return Some(caps);
+        }
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        self.next_captures()?.range(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_leftmost_first() {
+        let cases = [
+            ("a+", "baaac", Some(1..4)),
+            ("a*", "baaa", Some(0..0)),
+            ("ab?", "xxab", Some(2..4)),
+            ("é+", "aéé", Some(1..5)),
+            ("x$", "xx", Some(1..2)),
+            ("^x", "xx", Some(0..1)),
+            ("^x", "ax", None),
+            ("(?m)^x$", "a\nx\ny", Some(2..3)),
+            ("\\d{2,3}", "a12345", Some(1..4)),
+        ];
+        for (pattern, text, expected) in cases {
+            let fsm = Fsm::compile(pattern).unwrap();
+            assert_eq!(fsm.find(text), expected, "{} in {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn greedy_and_lazy_spans() {
+        // (greedy pattern, lazy pattern, text, greedy span, lazy span)
+        let cases = [
+            ("a.*b", "a.*?b", "xaxbxb", 1..6, 1..4),
+            ("a+", "a+?", "aaa", 0..3, 0..1),
+            ("a?", "a??", "ab", 0..1, 0..0),
+            ("a*", "a*?", "aa", 0..2, 0..0),
+            ("a{2,4}", "a{2,4}?", "aaaaa", 0..4, 0..2),
+            ("a{2,}", "a{2,}?", "aaaaa", 0..5, 0..2),
+            ("a{2}", "a{2}?", "aaa", 0..2, 0..2),
+            ("<.+>", "<.+?>", "<a><b>", 0..6, 0..3),
+            ("x*$", "x*?$", "axx", 1..3, 1..3),
+            ("(ab)*c", "(ab)*?c", "ababc", 0..5, 0..5),
+            ("é+", "é+?", "ééé", 0..6, 0..2),
+        ];
+        for (greedy, lazy, text, greedy_span, lazy_span) in cases {
+            let greedy_fsm = Fsm::compile(greedy).unwrap();
+            let lazy_fsm = Fsm::compile(lazy).unwrap();
+            assert_eq!(greedy_fsm.find(text), Some(greedy_span), "{}", greedy);
+            assert_eq!(lazy_fsm.find(text), Some(lazy_span), "{}", lazy);
+            // Whether there is a match does not depend on laziness.
+            assert_eq!(greedy_fsm.match_str(text), lazy_fsm.match_str(text));
+            assert_eq!(greedy_fsm.state_count(), lazy_fsm.state_count());
+        }
+
+        let caps = Fsm::compile("(a+?)(a*)").unwrap();
+        let caps = caps.captures("aaa").unwrap();
+        assert_eq!((caps.get(1), caps.get(2)), (Some("a"), Some("aa")));
+        let caps = Fsm::compile("(a*?)(a??)b").unwrap();
+        let caps = caps.captures("aab").unwrap();
+        assert_eq!((caps.get(1), caps.get(2)), (Some("a"), Some("a")));
+    }
+
+    #[test]
+    fn find_iter_skips_empty_after_match() {
+        let fsm = Fsm::compile("a*").unwrap();
+        let spans = fsm.find_iter("baab").collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..0, 1..3, 4..4]);
+        let fsm = Fsm::compile("").unwrap();
+        assert_eq!(fsm.find_iter("é").count(), 2);
+    }
+
+    #[test]
+    fn groups() {
+        let fsm = Fsm::compile("(?<year>\\d{4})-(\\d\\d)(-(\\d\\d))?").unwrap();
+        let caps = fsm.captures("on 2024-05 at").unwrap();
+        assert_eq!(caps.len(), 5);
+        assert_eq!(caps.get(0), Some("2024-05"));
+        assert_eq!(caps.name("year"), Some("2024"));
+        assert_eq!(caps.get(2), Some("05"));
+        assert_eq!(caps.get(4), None);
+        assert_eq!(caps.range(1), Some(3..7));
+
+        // The last iteration of a repeated group is kept.
+        assert!(Fsm::compile("(a)").unwrap().captures("x").is_none());
+        let fsm = Fsm::compile("(\\w)+").unwrap();
+        assert_eq!(fsm.captures("abc").unwrap().get(1), Some("c"));
+
+        for (pattern, msg) in [
+            ("(?<1a>x)", "invalid group name"),
+            ("(?<>x)", "invalid group name"),
+            ("(?P<a x)", "invalid group name"),
+            ("(?<a>x)(?P<a>y)", "duplicate group name"),
+        ] {
+            assert_eq!(Fsm::compile(pattern).err().map(|e| e.msg), Some(msg));
+        }
+    }
+
+    #[test]
+    fn expand() {
+        let fsm = Fsm::compile("(?P<first>\\w+) (\\w+)").unwrap();
+        let caps = fsm.captures("hello world").unwrap();
+        let cases = [
+            ("$2 $1", "world hello"),
+            ("${2}x", "worldx"),
+            ("$2x", ""),
+            ("$first!", "hello!"),
+            ("${first}s", "hellos"),
+            ("$$1 $", "$1 $"),
+            ("${3}", ""),
+            ("${oops", "${oops"),
+        ];
+        for (replacement, expected) in cases {
+            let mut dst = String::new();
+            caps.expand(replacement, &mut dst);
+            assert_eq!(dst, expected, "{}", replacement);
+        }
+    }
+
+    #[test]
+    fn columns_find_shortest() {
+        let fsm = Fsm::from_columns(Fsm::compile("ab+").unwrap().cs.clone());
+        assert_eq!(fsm.find("xabbb"), Some(1..3));
+        assert_eq!(fsm.find("xa"), None);
+    }
+}
@@ -0,0 +1,240 @@
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+/// A set of Unicode scalar values stored as sorted, non-overlapping ranges.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CharClass {
+    ranges: Vec<RangeInclusive<char>>,
+}
+
+impl CharClass {
+    pub fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    pub fn any() -> Self {
+        Self {
+            ranges: vec!['\0'..=char::MAX],
+        }
+    }
+
+    pub fn single(c: char) -> Self {
+        Self {
+            ranges: vec![c..=c],
+        }
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<char>] {
+        &self.ranges
+    }
+
+    /// The only member of a class holding exactly one character.
+    pub fn as_single(&self) -> Option<char> {
+        match self.ranges.as_slice() {
+            [r] if r.start() == r.end() => Some(*r.start()),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let i = self.ranges.partition_point(|r| *r.end() < c);
+        i < self.ranges.len() && *self.ranges[i].start() <= c
+    }
+
+    pub fn push(&mut self, range: RangeInclusive<char>) {
+        if range.start() > range.end() {
+            return;
+        }
+        self.ranges.push(range);
+        self.canonicalize();
+    }
+
+    pub fn union(&mut self, other: &CharClass) {
+        self.ranges.extend(other.ranges.iter().cloned());
+        self.canonicalize();
+    }
+
+    pub fn negate(&self) -> Self {
+        let mut ranges = Vec::new();
+        let mut next = Some('\0');
+        for r in self.ranges.iter() {
+            if let Some(lo) = next {
+                if lo < *r.start() {
+                    ranges.push(lo..=prev_char(*r.start()).unwrap());
+                }
+            }
+            next = next_char(*r.end());
+        }
+        if let Some(lo) = next {
+            ranges.push(lo..=char::MAX);
+        }
+        Self { ranges }
+    }
+
+    /// Adds the other case of every letter, using the simple (one character
+    /// to one character) case mappings.
+    pub fn case_fold(&self) -> Self {
+        let pairs = case_pairs();
+        let mut folded = self.clone();
+        for r in self.ranges.iter() {
+            let from = pairs.partition_point(|&(c, _)| c < *r.start());
+            for &(c, other) in pairs[from..].iter() {
+                if c > *r.end() {
+                    break;
+                }
+                folded.ranges.push(other..=other);
+            }
+        }
+        folded.canonicalize();
+        folded
+    }
+
+    /// Unicode property classes usable as `\p{Name}` and `\P{Name}`.
+    pub fn property(name: &str) -> Option<Self> {
+        static ALPHABETIC: OnceLock<CharClass> = OnceLock::new();
+        static ALPHANUMERIC: OnceLock<CharClass> = OnceLock::new();
+        static CONTROL: OnceLock<CharClass> = OnceLock::new();
+        static LOWERCASE: OnceLock<CharClass> = OnceLock::new();
+        static NUMERIC: OnceLock<CharClass> = OnceLock::new();
+        static UPPERCASE: OnceLock<CharClass> = OnceLock::new();
+        static WHITE_SPACE: OnceLock<CharClass> = OnceLock::new();
+
+        let (cell, f): (_, fn(char) -> bool) = match name {
+            "Alphabetic" | "Alpha" => (&ALPHABETIC, char::is_alphabetic),
+            "Alphanumeric" | "Alnum" => (&ALPHANUMERIC, char::is_alphanumeric),
+            "Control" | "Cc" => (&CONTROL, char::is_control),
+            "Lowercase" | "Lower" => (&LOWERCASE, char::is_lowercase),
+            "Numeric" | "N" => (&NUMERIC, char::is_numeric),
+            "Uppercase" | "Upper" => (&UPPERCASE, char::is_uppercase),
+            "White_Space" | "Space" => (&WHITE_SPACE, char::is_whitespace),
+            _ => return None,
+        };
+        Some(cell.get_or_init(|| Self::from_predicate(f)).clone())
+    }
+
+    /// `\d`, `\w` and `\s`; the latter two follow the Unicode properties.
+    pub fn perl(c: char) -> Option<Self> {
+        let class = match c.to_ascii_lowercase() {
+            'd' => Self {
+                ranges: vec!['0'..='9'],
+            },
+            'w' => {
+                let mut class = Self::property("Alphanumeric").unwrap();
+                class.push('_'..='_');
+                class
+            }
+            's' => Self::property("White_Space").unwrap(),
+            _ => return None,
+        };
+        if c.is_ascii_uppercase() {
+            Some(class.negate())
+        } else {
+            Some(class)
+        }
+    }
+
+    fn from_predicate(f: fn(char) -> bool) -> Self {
+        let mut ranges: Vec<RangeInclusive<char>> = Vec::new();
+        for c in ('\0'..=char::MAX).filter(|&c| f(c)) {
+            match ranges.last_mut() {
+                Some(r) if next_char(*r.end()) == Some(c) => *r = *r.start()..=c,
+                _ => ranges.push(c..=c),
+            }
+        }
+        Self { ranges }
+    }
+
+    fn canonicalize(&mut self) {
+        self.ranges.sort_by_key(|r| *r.start());
+        let mut merged: Vec<RangeInclusive<char>> = Vec::with_capacity(self.ranges.len());
+        for r in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if next_char(*last.end()).is_none_or(|n| n >= *r.start()) => {
+                    if r.end() > last.end() {
+                        *last = *last.start()..=*r.end();
+                    }
+                }
+                _ => merged.push(r),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+/// Every character with a simple case mapping, paired with the characters it
+/// maps to or is mapped from, sorted by the first one.
+fn case_pairs() -> &'static [(char, char)] {
+    static PAIRS: OnceLock<Vec<(char, char)>> = OnceLock::new();
+    PAIRS.get_or_init(|| {
+        let mut pairs = Vec::new();
+        for c in '\0'..=char::MAX {
+            let lower = only(c.to_lowercase());
+            let upper = only(c.to_uppercase());
+            for other in [lower, upper]
+                .into_iter()
+                .flatten()
+                .filter(|&other| other != c)
+            {
+                pairs.push((c, other));
+                pairs.push((other, c));
+            }
+        }
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    })
+}
+
+/// The character of a case mapping made of exactly one.
+fn only(mut mapped: impl Iterator<Item = char>) -> Option<char> {
+    match (mapped.next(), mapped.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// The scalar value following `c`, skipping the surrogate gap.
+pub fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// The scalar value preceding `c`, skipping the surrogate gap.
+pub fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        _ => char::from_u32(c as u32 - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negate_round_trip() {
+        let mut class = CharClass::new();
+        class.push('a'..='c');
+        class.push('x'..='z');
+        class.push('b'..='d');
+        assert_eq!(class.ranges(), &['a'..='d', 'x'..='z']);
+
+        let negated = class.negate();
+        assert_eq!(negated.ranges(), &['\0'..='`', 'e'..='w', '{'..=char::MAX]);
+        assert_eq!(negated.negate(), class);
+    }
+
+    #[test]
+    fn unicode_properties() {
+        let alpha = CharClass::property("Alphabetic").unwrap();
+        let contains = |c| alpha.ranges().iter().any(|r| r.contains(&c));
+        assert!(contains('é'));
+        assert!(contains('中'));
+        assert!(!contains('1'));
+        assert!(CharClass::property("Bogus").is_none());
+    }
+}
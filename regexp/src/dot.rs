@@ -0,0 +1,172 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::class::{prev_char, CharClass};
+use crate::{Fsm, FsmElement};
+
+impl Fsm {
+    /// Renders the automaton as a Graphviz digraph. Transitions sharing a
+    /// target are grouped into character ranges, the failed state is left
+    /// out, the accepting state is double-circled, look back transitions are
+    /// dashed and `$` marks the transition taken at the end of input.
+    pub fn to_dot(&self) -> String {
+        let accept = self.cs.len();
+        let node = |state: usize| {
+            if state >= accept {
+                "accept".to_string()
+            } else {
+                state.to_string()
+            }
+        };
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph Fsm {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [shape=circle];").unwrap();
+        writeln!(dot, "    start [shape=point];").unwrap();
+        writeln!(dot, "    accept [shape=doublecircle];").unwrap();
+        writeln!(dot, "    start -> {};", node(1)).unwrap();
+
+        for (state, col) in self.cs.iter().enumerate().skip(1) {
+            let mut edges: Vec<(FsmElement, CharClass)> = Vec::new();
+            for (i, &(start, t)) in col.ts.iter().enumerate() {
+                if t.next == 0 {
+                    continue;
+                }
+                let end = match col.ts.get(i + 1) {
+                    Some(&(next, _)) => prev_char(next).unwrap(),
+                    None => char::MAX,
+                };
+                match edges.iter_mut().find(|(e, _)| *e == t) {
+                    Some((_, class)) => class.push(start..=end),
+                    None => {
+                        let mut class = CharClass::new();
+                        class.push(start..=end);
+                        edges.push((t, class));
+                    }
+                }
+            }
+
+            for (t, class) in edges.iter() {
+                let style = if t.offset == 0 { ", style=dashed" } else { "" };
+                writeln!(
+                    dot,
+                    "    {} -> {} [label=\"{}\"{}];",
+                    state,
+                    node(t.next),
+                    class_label(class),
+                    style
+                )
+                .unwrap();
+            }
+            if col.end.next != 0 {
+                writeln!(
+                    dot,
+                    "    {} -> {} [label=\"$\"];",
+                    state,
+                    node(col.end.next)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// Lists the ranges of `class`, or those of its complement after a `^` when
+/// that is shorter.
+fn class_label(class: &CharClass) -> String {
+    let negated = class.negate();
+    let (prefix, ranges) = if negated.ranges().len() < class.ranges().len() {
+        ("^", negated.ranges())
+    } else {
+        ("", class.ranges())
+    };
+    let ranges = ranges.iter().map(range_label).collect::<Vec<_>>();
+    if prefix.is_empty() && ranges.len() == 1 && !ranges[0].contains('-') {
+        ranges[0].clone()
+    } else {
+        format!("[{}{}]", prefix, ranges.join(""))
+    }
+}
+
+fn range_label(range: &RangeInclusive<char>) -> String {
+    if range.start() == range.end() {
+        char_label(*range.start())
+    } else {
+        format!(
+            "{}-{}",
+            char_label(*range.start()),
+            char_label(*range.end())
+        )
+    }
+}
+
+fn char_label(c: char) -> String {
+    match c {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\\\\\".to_string(),
+        '-' | '[' | ']' | '^' => format!("\\\\{}", c),
+        c if c.is_control() || (c.is_whitespace() && c != ' ') => {
+            format!("U+{:04X}", c as u32)
+        }
+        c => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FsmColumn;
+
+    #[test]
+    fn minimized_graph() {
+        let dot = Fsm::compile("[a-c]+x$").unwrap().to_dot();
+        let expected = "\
+digraph Fsm {
+    rankdir=LR;
+    node [shape=circle];
+    start [shape=point];
+    accept [shape=doublecircle];
+    start -> 1;
+    1 -> 2 [label=\"[a-c]\"];
+    2 -> 2 [label=\"[a-c]\"];
+    2 -> 3 [label=\"x\"];
+    3 -> accept [label=\"$\"];
+}
+";
+        assert_eq!(dot, expected);
+    }
+
+    #[test]
+    fn look_back_and_ranges() {
+        // `a*-$` the way `*` used to be compiled: any character but `a`
+        // leaves state 1 for state 2 and is looked at again there.
+        let mut star = FsmColumn::new();
+        star.ts = vec![
+            ('\0', FsmElement::new(2, 0)),
+            ('a', FsmElement::new(1, 1)),
+            ('b', FsmElement::new(2, 0)),
+        ];
+        star.end = FsmElement::new(2, 0);
+        let mut dash = FsmColumn::new();
+        dash.ts = vec![
+            ('\0', FsmElement::new(0, 0)),
+            ('-', FsmElement::new(3, 1)),
+            ('.', FsmElement::new(0, 0)),
+        ];
+        let mut end = FsmColumn::new();
+        end.end = FsmElement::new(4, 1);
+        let fsm = Fsm::from_columns(vec![FsmColumn::new(), star, dash, end]);
+        assert!(fsm.match_str("aa-"));
+        assert!(!fsm.match_str("aab"));
+
+        let dot = fsm.to_dot();
+        assert!(dot.contains("1 -> 1 [label=\"a\"];"));
+        assert!(dot.contains("1 -> 2 [label=\"[^a]\", style=dashed];"));
+        assert!(dot.contains("1 -> 2 [label=\"$\"];"));
+        assert!(dot.contains("2 -> 3 [label=\"[\\\\-]\"];"));
+        assert!(dot.contains("3 -> accept [label=\"$\"];"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Extended state machines: besides its state, a machine holds a context
+//! which guards read, and which transition actions and state hooks update.
+//! Actions produce outputs (Mealy), and hooks run when a state is entered or
+//! left (Moore).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+
+type Guard<C> = Box<dyn Fn(&C) -> bool>;
+type Action<C, O> = Box<dyn FnMut(&mut C) -> O>;
+type Hook<C> = Box<dyn FnMut(&mut C)>;
+
+/// A transition from `from` to `to` on `event`, taken only if its guard
+/// holds, and producing the output of its action.
+pub struct Transition<S, E, C, O> {
+    from: S,
+    event: E,
+    to: S,
+    guard: Option<Guard<C>>,
+    action: Option<Action<C, O>>,
+}
+
+impl<S, E, C, O> Transition<S, E, C, O> {
+    pub fn new(from: S, event: E, to: S) -> Self {
+        Self {
+            from,
+            event,
+            to,
+            guard: None,
+            action: None,
+        }
+    }
+
+    pub fn guard(mut self, guard: impl Fn(&C) -> bool + 'static) -> Self {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    pub fn action(mut self, action: impl FnMut(&mut C) -> O + 'static) -> Self {
+        self.action = Some(Box::new(action));
+        self
+    }
+}
+
+/// No transition from `state` on `event` has a guard which holds. The
+/// machine stays where it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unhandled<S, E> {
+    pub state: S,
+    pub event: E,
+}
+
+impl<S: fmt::Display, E: fmt::Display> fmt::Display for Unhandled<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "event {} is not handled in state {}",
+            self.event, self.state
+        )
+    }
+}
+
+impl<S: fmt::Debug + fmt::Display, E: fmt::Debug + fmt::Display> Error for Unhandled<S, E> {}
+
+/// A running extended state machine with states `S`, events `E`, context
+/// `C` and outputs `O`.
+pub struct Machine<S, E, C, O> {
+    state: S,
+    context: C,
+    transitions: Vec<Transition<S, E, C, O>>,
+    on_entry: HashMap<S, Vec<Hook<C>>>,
+    on_exit: HashMap<S, Vec<Hook<C>>>,
+}
+
+impl<S, E, C, O> Machine<S, E, C, O>
+where
+    S: Copy + Eq + Hash,
+    E: Copy + Eq,
+{
+    /// A machine in `initial`, without running its entry hooks.
+    pub fn new(initial: S, context: C) -> Self {
+        Self {
+            state: initial,
+            context,
+            transitions: Vec::new(),
+            on_entry: HashMap::new(),
+            on_exit: HashMap::new(),
+        }
+    }
+
+    /// Adds a transition. Transitions are tried in the order they were
+    /// added, and the first one whose guard holds is taken.
+    pub fn transition(&mut self, transition: Transition<S, E, C, O>) -> &mut Self {
+        self.transitions.push(transition);
+        self
+    }
+
+    /// Runs `hook` whenever the machine moves into `state`.
+    pub fn on_entry(&mut self, state: S, hook: impl FnMut(&mut C) + 'static) -> &mut Self {
+        self.on_entry.entry(state).or_default().push(Box::new(hook));
+        self
+    }
+
+    /// Runs `hook` whenever the machine moves out of `state`.
+    pub fn on_exit(&mut self, state: S, hook: impl FnMut(&mut C) + 'static) -> &mut Self {
+        self.on_exit.entry(state).or_default().push(Box::new(hook));
+        self
+    }
+
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+
+    /// Takes the transition for `event` and returns the output of its
+    /// action, if it has one. The exit hooks of the current state run
+    /// first, then the action, then the entry hooks of the next state.
+    /// Hooks do not run when the transition leads back to the same state.
+    pub fn handle(&mut self, event: E) -> Result<Option<O>, Unhandled<S, E>> {
+        let state = self.state;
+        let context = &mut self.context;
+        let transition = self
+            .transitions
+            .iter_mut()
+            .find(|t| {
+                t.from == state && t.event == event && t.guard.as_ref().is_none_or(|g| g(context))
+            })
+            .ok_or(Unhandled { state, event })?;
+
+        let to = transition.to;
+        if to != state {
+            for hook in self.on_exit.get_mut(&state).into_iter().flatten() {
+                hook(context);
+            }
+        }
+        let output = transition.action.as_mut().map(|action| action(context));
+        if to != state {
+            for hook in self.on_entry.get_mut(&to).into_iter().flatten() {
+                hook(context);
+            }
+        }
+        self.state = to;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Light {
+        Off,
+        On,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Switch {
+        Flip,
+        Break,
+    }
+
+    #[derive(Default)]
+    struct Bulb {
+        flips: u32,
+        log: Vec<&'static str>,
+    }
+
+    fn lamp() -> Machine<Light, Switch, Bulb, u32> {
+        let mut lamp = Machine::new(Light::Off, Bulb::default());
+        lamp.transition(
+            Transition::new(Light::Off, Switch::Flip, Light::On)
+                .guard(|bulb: &Bulb| bulb.flips < 2)
+                .action(|bulb: &mut Bulb| {
+                    bulb.flips += 1;
+                    bulb.log.push("flip on");
+                    bulb.flips
+                }),
+        )
+        .transition(Transition::new(Light::On, Switch::Flip, Light::Off))
+        .transition(Transition::new(Light::On, Switch::Break, Light::On))
+        .on_entry(Light::On, |bulb| bulb.log.push("enter on"))
+        .on_exit(Light::On, |bulb| bulb.log.push("exit on"))
+        .on_exit(Light::Off, |bulb| bulb.log.push("exit off"));
+        lamp
+    }
+
+    #[test]
+    fn outputs_hooks_and_guards() {
+        let mut lamp = lamp();
+        assert_eq!(lamp.handle(Switch::Flip), Ok(Some(1)));
+        assert_eq!(lamp.handle(Switch::Break), Ok(None));
+        assert_eq!(lamp.handle(Switch::Flip), Ok(None));
+        assert_eq!(lamp.handle(Switch::Flip), Ok(Some(2)));
+        assert_eq!(lamp.handle(Switch::Flip), Ok(None));
+        assert_eq!(
+            lamp.context().log,
+            [
+                "exit off", "flip on", "enter on", "exit on", "exit off", "flip on", "enter on",
+                "exit on"
+            ]
+        );
+
+        // The guard stops a third flip, and the machine stays off.
+        assert_eq!(
+            lamp.handle(Switch::Flip),
+            Err(Unhandled {
+                state: Light::Off,
+                event: Switch::Flip
+            })
+        );
+        assert_eq!(lamp.state(), Light::Off);
+        assert_eq!(
+            lamp.handle(Switch::Break).err().map(|e| e.state),
+            Some(Light::Off)
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Shell globs compiled into the crate's automaton.
+//!
+//! - `*` matches any sequence of characters and `?` any one character;
+//! - `[abc]`, `[a-z]` and `[!abc]` (or `[^abc]`) match one character of a
+//!   set, or one not in it;
+//! - `{a,b}` matches one of the comma-separated globs, which may nest;
+//! - `\` takes the next character literally.
+//!
+//! With `GlobBuilder::literal_separator`, none of these match `/` and `**`
+//! crosses directories: `**/` matches any number of leading directories and
+//! a trailing `/**` everything below. Anywhere else `**` is a `*`.
+//!
+//! ```
+//! use regexp::glob::{Glob, GlobBuilder};
+//!
+//! assert!(Glob::compile("*.{rs,toml}").unwrap().matches("src/lib.rs"));
+//! let glob = GlobBuilder::new("src/**/*.rs").literal_separator(true).build().unwrap();
+//! assert!(glob.matches("src/lib.rs"));
+//! assert!(glob.matches("src/bin/bench.rs"));
+//! assert!(!glob.matches("src/README.md"));
+//! ```
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::class::CharClass;
+use crate::nfa::Nfa;
+use crate::parse::{Ast, Look};
+use crate::{CompileError, Fsm, RegexSet};
+
+/// A glob matching whole paths.
+pub struct Glob {
+    glob: String,
+    fsm: Fsm,
+}
+
+/// Compiles a glob with options.
+#[derive(Debug, Clone)]
+pub struct GlobBuilder {
+    glob: String,
+    literal_separator: bool,
+}
+
+impl GlobBuilder {
+    pub fn new(glob: &str) -> Self {
+        Self {
+            glob: glob.to_string(),
+            literal_separator: false,
+        }
+    }
+
+    /// Only `**` matches `/`.
+    pub fn literal_separator(&mut self, yes: bool) -> &mut Self {
+        self.literal_separator = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Glob, CompileError> {
+        let mut parser = Parser {
+            chars: self.glob.char_indices().peekable(),
+            literal_separator: self.literal_separator,
+        };
+        let (ast, _) = parser.parse_sequence(false, true)?;
+        let ast = Ast::Concat(vec![ast, Ast::Look(Look::EndText)]);
+        let mut fsm = Fsm::from_nfa(Nfa::new(&ast)?)?;
+        fsm.minimize();
+        Ok(Glob {
+            glob: self.glob.clone(),
+            fsm,
+        })
+    }
+}
+
+impl Glob {
+    /// Compiles `glob` with the default options, where `*` matches `/`.
+    pub fn compile(glob: &str) -> Result<Self, CompileError> {
+        GlobBuilder::new(glob).build()
+    }
+
+    /// Whether the whole of `path` matches.
+    pub fn matches(&self, path: &str) -> bool {
+        self.fsm.match_str(path)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    /// The automaton the glob was compiled into.
+    pub fn fsm(&self) -> &Fsm {
+        &self.fsm
+    }
+}
+
+/// Several globs matched at once, in a single scan of the path.
+pub struct GlobSet {
+    set: RegexSet,
+}
+
+impl GlobSet {
+    pub fn new(globs: impl IntoIterator<Item = Glob>) -> Result<Self, CompileError> {
+        let fsms = globs.into_iter().map(|glob| glob.fsm).collect::<Vec<_>>();
+        Ok(Self {
+            set: RegexSet::from_fsms(&fsms)?,
+        })
+    }
+
+    /// Indices of the globs matching `path`, in increasing order.
+    pub fn matches(&self, path: &str) -> Vec<usize> {
+        self.set.matches(path)
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.set.is_match(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    literal_separator: bool,
+}
+
+impl Parser<'_> {
+    /// Characters `*`, `?` and bracket expressions may match.
+    fn component(&self, class: CharClass) -> CharClass {
+        if self.literal_separator {
+            let mut outside = class.negate();
+            outside.union(&CharClass::single('/'));
+            outside.negate()
+        } else {
+            class
+        }
+    }
+
+    /// Parses up to the end of the glob, or of the alternative when `nested`
+    /// in `{...}`. `component_start` tells whether the sequence starts a path
+    /// component, and the returned flag whether it ends right before one.
+    fn parse_sequence(
+        &mut self,
+        nested: bool,
+        mut component_start: bool,
+    ) -> Result<(Ast, bool), CompileError> {
+        let mut items = Vec::new();
+        while let Some(&(pos, c)) = self.chars.peek() {
+            if nested && (c == ',' || c == '}') {
+                break;
+            }
+            self.chars.next();
+            let mut separator = c == '/';
+            let ast = match c {
+                '*' => {
+                    let double = self.chars.next_if(|&(_, c)| c == '*').is_some();
+                    let end = match self.chars.peek() {
+                        None => true,
+                        Some(&(_, c)) => nested && (c == ',' || c == '}'),
+                    };
+                    let any = Ast::Repeat {
+                        ast: Box::new(Ast::Class(CharClass::any())),
+                        min: 0,
+                        max: None,
+                        greedy: true,
+                    };
+                    if double && self.literal_separator && component_start && end {
+                        any
+                    } else if double
+                        && self.literal_separator
+                        && component_start
+                        && self.chars.next_if(|&(_, c)| c == '/').is_some()
+                    {
+                        // `**/`: any number of directories.
+                        separator = true;
+                        Ast::Repeat {
+                            ast: Box::new(Ast::Concat(vec![
+                                self.star(),
+                                Ast::Class(CharClass::single('/')),
+                            ])),
+                            min: 0,
+                            max: None,
+                            greedy: true,
+                        }
+                    } else {
+                        self.star()
+                    }
+                }
+                '?' => Ast::Class(self.component(CharClass::any())),
+                '[' => Ast::Class(self.parse_bracket(pos)?),
+                '{' => {
+                    let (ast, ends_component) = self.parse_alternatives(pos, component_start)?;
+                    separator = ends_component;
+                    ast
+                }
+                '\\' => {
+                    let (_, c) = self
+                        .chars
+                        .next()
+                        .ok_or(CompileError::new(pos, "trailing backslash"))?;
+                    Ast::Class(CharClass::single(c))
+                }
+                c => Ast::Class(CharClass::single(c)),
+            };
+            component_start = separator;
+            items.push(ast);
+        }
+        Ok((Ast::Concat(items), component_start))
+    }
+
+    fn star(&self) -> Ast {
+        Ast::Repeat {
+            ast: Box::new(Ast::Class(self.component(CharClass::any()))),
+            min: 0,
+            max: None,
+            greedy: true,
+        }
+    }
+
+    /// Parses `{a,b}` after its `{`. What follows it starts a path component
+    /// only if every alternative ends right before one.
+    fn parse_alternatives(
+        &mut self,
+        pos: usize,
+        component_start: bool,
+    ) -> Result<(Ast, bool), CompileError> {
+        let mut alternatives = Vec::new();
+        let mut ends_component = true;
+        loop {
+            let (ast, ends) = self.parse_sequence(true, component_start)?;
+            alternatives.push(ast);
+            ends_component &= ends;
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok((Ast::Alternate(alternatives), ends_component)),
+                _ => return Err(CompileError::new(pos, "unclosed alternation")),
+            }
+        }
+    }
+
+    /// Parses `[abc]`, `[a-z]` or `[!abc]` after its `[`. A `]` right after
+    /// the `[` or the negation is taken literally.
+    fn parse_bracket(&mut self, pos: usize) -> Result<CharClass, CompileError> {
+        let negated = self.chars.next_if(|&(_, c)| c == '!' || c == '^').is_some();
+        let mut class = CharClass::new();
+        let mut first = true;
+        loop {
+            let (_, c) = self
+                .chars
+                .next()
+                .ok_or(CompileError::new(pos, "unterminated bracket expression"))?;
+            let c = match c {
+                ']' if !first => break,
+                '\\' => {
+                    self.chars
+                        .next()
+                        .ok_or(CompileError::new(pos, "unterminated bracket expression"))?
+                        .1
+                }
+                c => c,
+            };
+            first = false;
+            let is_range = {
+                let mut lookahead = self.chars.clone();
+                lookahead.next().is_some_and(|(_, c)| c == '-')
+                    && lookahead.next().is_some_and(|(_, c)| c != ']')
+            };
+            if is_range {
+                self.chars.next();
+                let (p, mut end) = self.chars.next().unwrap();
+                if end == '\\' {
+                    end = self
+                        .chars
+                        .next()
+                        .ok_or(CompileError::new(pos, "unterminated bracket expression"))?
+                        .1;
+                }
+                if end < c {
+                    return Err(CompileError::new(p, "invalid range"));
+                }
+                class.push(c..=end);
+            } else {
+                class.push(c..=c);
+            }
+        }
+        let class = if negated { class.negate() } else { class };
+        Ok(self.component(class))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_glob(glob: &str) -> Glob {
+        GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn wildcards() {
+        let cases = [
+            ("*.rs", "main.rs", true),
+            ("*.rs", "src/main.rs", true),
+            ("*.rs", "main.rs.bak", false),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("?", "é", true),
+            ("[abc]x", "bx", true),
+            ("[a-c]x", "dx", false),
+            ("[!a-c]x", "dx", true),
+            ("[^a-c]x", "ax", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("file.{rs,toml}", "file.toml", true),
+            ("file.{rs,toml}", "file.md", false),
+            ("{a,b{c,d}}e", "bde", true),
+            ("{,x}y", "y", true),
+            ("\\*", "*", true),
+            ("\\*", "x", false),
+            ("a,b}", "a,b}", true),
+            ("", "", true),
+        ];
+        for (glob, path, expected) in cases {
+            assert_eq!(
+                Glob::compile(glob).unwrap().matches(path),
+                expected,
+                "{} {}",
+                glob,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn literal_separator() {
+        let cases = [
+            ("*.rs", "main.rs", true),
+            ("*.rs", "src/main.rs", false),
+            ("src/?", "src/a", true),
+            ("src?a", "src/a", false),
+            ("src[/]a", "src/a", false),
+            ("src[!a]a", "src/a", false),
+            ("**/*.rs", "main.rs", true),
+            ("**/*.rs", "src/bin/main.rs", true),
+            ("src/**/*.rs", "src/main.rs", true),
+            ("src/**/*.rs", "src/a/b/main.rs", true),
+            ("src/**/*.rs", "lib/main.rs", false),
+            ("src/**", "src/a/b", true),
+            ("src/**", "src", false),
+            ("a**b", "axxb", true),
+            ("a**b", "ax/xb", false),
+            ("{src/**,*.md}", "src/x/y", true),
+            ("{src/**,*.md}", "doc/x.md", false),
+            // Only an alternative which starts a component may hold `**`.
+            ("x{**,y}", "xa/b", false),
+            ("x{**,y}", "xab", true),
+            ("{a/,b/}**/c", "a/d/e/c", true),
+            ("{a/,b}**/c", "bd/c", true),
+            ("{a/,b}**/c", "bd/e/c", false),
+        ];
+        for (glob, path, expected) in cases {
+            assert_eq!(path_glob(glob).matches(path), expected, "{} {}", glob, path);
+        }
+        // Without the option `**` is a `*`, which crosses `/` anyway.
+        assert!(Glob::compile("src/**.rs").unwrap().matches("src/a/b.rs"));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("a{b,c", (1, "unclosed alternation")),
+            ("[ab", (0, "unterminated bracket expression")),
+            ("[z-a]", (3, "invalid range")),
+            ("ab\\", (2, "trailing backslash")),
+        ];
+        for (glob, expected) in cases {
+            let e = Glob::compile(glob).err().unwrap();
+            assert_eq!((e.pos, e.msg), expected, "{}", glob);
+        }
+    }
+
+    #[test]
+    fn glob_set() {
+        let globs = ["*.rs", "src/**", "**/test_*", "Cargo.{toml,lock}"];
+        let set = GlobSet::new(globs.iter().map(|glob| path_glob(glob))).unwrap();
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.matches("src/lib.rs"), vec![1]);
+        assert_eq!(set.matches("main.rs"), vec![0]);
+        assert_eq!(set.matches("src/test_glob.rs"), vec![1, 2]);
+        assert_eq!(set.matches("Cargo.lock"), vec![3]);
+        assert!(!set.is_match("README.md"));
+        assert_eq!(path_glob("src/**").as_str(), "src/**");
+    }
+}
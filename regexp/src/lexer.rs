@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use crate::nfa::{Context, Nfa};
+use crate::parse::{self, Ast, Flags};
+use crate::table::{CharClasses, Transitions};
+use crate::{CompileError, MAX_STATES};
+
+/// Collects the rules of a lexer. Each rule is a pattern with the kind of
+/// token it produces, or a skip rule whose matches are dropped.
+///
+/// ```
+/// use regexp::LexerBuilder;
+///
+/// let lexer = LexerBuilder::new()
+///     .rule("let", "let")
+///     .rule("ident", "[a-z]+")
+///     .rule("eq", "=")
+///     .rule("int", "\\d+")
+///     .skip("\\s+")
+///     .build()
+///     .unwrap();
+/// let tokens = lexer.lex("let letter = 42").collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(tokens, vec![("let", 0..3), ("ident", 4..10), ("eq", 11..12), ("int", 13..15)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LexerBuilder<K> {
+    rules: Vec<(Option<K>, String)>,
+}
+
+impl<K: Clone> Default for LexerBuilder<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone> LexerBuilder<K> {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a rule producing tokens of `kind`. When rules match equally
+    /// long input, the one added first wins.
+    pub fn rule(&mut self, kind: K, pattern: &str) -> &mut Self {
+        self.rules.push((Some(kind), pattern.to_string()));
+        self
+    }
+
+    /// Adds a rule whose matches, such as whitespace or comments, are
+    /// skipped.
+    pub fn skip(&mut self, pattern: &str) -> &mut Self {
+        self.rules.push((None, pattern.to_string()));
+        self
+    }
+
+    /// Compiles all the rules into one automaton. A state of it records the
+    /// NFA states every rule may be in, and the first rule which has matched
+    /// there. Rules may not match the empty string nor use `^` or `$`.
+    pub fn build(&self) -> Result<LexerTable<K>, RuleError> {
+        let nfas = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, (_, pattern))| {
+                compile_rule(pattern).map_err(|error| RuleError {
+                    rule: Some(i),
+                    error,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Classes of characters which every rule treats the same.
+        let alphabets = nfas.iter().map(|nfa| nfa.alphabet().0).collect::<Vec<_>>();
+        let mut starts = alphabets
+            .iter()
+            .flat_map(|segments| segments.iter().map(|&(start, _)| start))
+            .collect::<Vec<_>>();
+        starts.push('\0');
+        starts.sort();
+        starts.dedup();
+        let mut groups: HashMap<Vec<usize>, u32> = HashMap::new();
+        let mut reps = Vec::new();
+        let segments = starts
+            .iter()
+            .map(|&start| {
+                let key = alphabets
+                    .iter()
+                    .map(|segments| {
+                        let i = segments.partition_point(|&(s, _)| s <= start);
+                        segments[i - 1].1
+                    })
+                    .collect::<Vec<_>>();
+                let group = *groups.entry(key).or_insert_with(|| {
+                    reps.push(start);
+                    reps.len() as u32 - 1
+                });
+                (start, group)
+            })
+            .collect::<Vec<_>>();
+
+        let ctx = Context::default();
+        let dead = vec![Vec::new(); nfas.len()];
+        let start = nfas
+            .iter()
+            .map(|nfa| nfa.closure([nfa.start], ctx))
+            .collect::<Vec<_>>();
+        let mut sets = vec![dead.clone(), start.clone()];
+        let mut ids = HashMap::from([(dead, 0), (start, 1)]);
+        let mut cells = vec![0; reps.len()];
+        let mut state = 1;
+        while state < sets.len() {
+            for &c in reps.iter() {
+                let next = sets[state]
+                    .iter()
+                    .zip(nfas.iter())
+                    .map(|(set, nfa)| nfa.closure(nfa.step(set, c), ctx))
+                    .collect::<Vec<_>>();
+                let id = match ids.get(&next) {
+                    Some(&id) => id,
+                    None => {
+                        if sets.len() >= MAX_STATES {
+                            return Err(RuleError {
+                                rule: None,
+                                error: CompileError::new(0, "automaton is too large"),
+                            });
+                        }
+                        ids.insert(next.clone(), sets.len());
+                        sets.push(next);
+                        sets.len() - 1
+                    }
+                };
+                cells.push(id);
+            }
+            state += 1;
+        }
+
+        Ok(LexerTable {
+            kinds: self.rules.iter().map(|(kind, _)| kind.clone()).collect(),
+            classes: CharClasses::new(&segments, reps.len()),
+            ts: Transitions::new(&cells),
+            accepts: sets
+                .iter()
+                .map(|set| {
+                    set.iter()
+                        .zip(nfas.iter())
+                        .position(|(set, nfa)| nfa.is_match(set))
+                })
+                .collect(),
+        })
+    }
+}
+
+fn has_look(ast: &Ast) -> bool {
+    match ast {
+        Ast::Look(_) => true,
+        Ast::Concat(items) | Ast::Alternate(items) => items.iter().any(has_look),
+        Ast::Repeat { ast, .. }
+        | Ast::Group(ast)
+        | Ast::Capture { ast, .. }
+        | Ast::LookAround { ast, .. } => has_look(ast),
+        Ast::Empty | Ast::Class(_) | Ast::BackRef { .. } => false,
+    }
+}
+
+/// The compiled rules of a lexer.
+#[derive(Debug, Clone)]
+pub struct LexerTable<K> {
+    /// Kind of every rule, `None` for skip rules.
+    kinds: Vec<Option<K>>,
+    classes: CharClasses,
+    ts: Transitions,
+    /// The rule matched by the input leading to every state, if any.
+    accepts: Vec<Option<usize>>,
+}
+
+impl<K: Clone> LexerTable<K> {
+    /// Splits `input` into tokens.
+    pub fn lex<'a>(&'a self, input: &'a str) -> Lexer<'a, K> {
+        Lexer {
+            table: self,
+            input,
+            pos: 0,
+            failed: false,
+        }
+    }
+
+    /// Longest match of any rule at `pos`, with the rule.
+    fn longest_match(&self, input: &str, pos: usize) -> Option<(usize, usize)> {
+        let mut state = 1;
+        let mut longest = None;
+        for (i, c) in input[pos..].char_indices() {
+            state = self
+                .ts
+                .get(state * self.classes.count() + self.classes.get(c));
+            if state == 0 {
+                break;
+            }
+            if let Some(rule) = self.accepts[state] {
+                longest = Some((rule, pos + i + c.len_utf8()));
+            }
+        }
+        longest
+    }
+}
+
+fn compile_rule(pattern: &str) -> Result<Nfa, CompileError> {
+    let ast = parse::parse(pattern, Flags::default())?;
+    if has_look(&ast) {
+        return Err(CompileError::new(
+            0,
+            "assertions are not supported by the lexer",
+        ));
+    }
+    let nfa = Nfa::new(&ast)?;
+    if nfa.is_match(&nfa.closure([nfa.start], Context::default())) {
+        return Err(CompileError::new(0, "rule matches the empty string"));
+    }
+    Ok(nfa)
+}
+
+/// Rules which cannot be compiled into a lexer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    /// Index of the rule at fault, counting skip rules too, in the order the
+    /// rules were added. `None` when the rules only fail together.
+    pub rule: Option<usize>,
+    pub error: CompileError,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rule {
+            Some(rule) => write!(f, "rule {}: {}", rule, self.error),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+impl Error for RuleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Input no rule matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexError {
+    /// Byte offset of the input.
+    pub pos: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unexpected input at byte {}", self.pos)
+    }
+}
+
+impl Error for LexError {}
+
+/// Iterator over the tokens of an input, as their kind and byte range. It
+/// ends after the first error.
+pub struct Lexer<'a, K> {
+    table: &'a LexerTable<K>,
+    input: &'a str,
+    pos: usize,
+    failed: bool,
+}
+
+impl<K: Clone> Iterator for Lexer<'_, K> {
+    type Item = Result<(K, Range<usize>), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.input.len() && !self.failed {
+            let Some((rule, end)) = self.table.longest_match(self.input, self.pos) else {
+                self.failed = true;
+                return Some(Err(LexError { pos: self.pos }));
+            };
+            let start = self.pos;
+            self.pos = end;
+            if let Some(kind) = &self.table.kinds[rule] {
+                return Some(Ok((kind.clone(), start..end)));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        Let,
+        Ident,
+        Int,
+        Eq,
+        EqEq,
+        Str,
+    }
+
+    fn config_lexer() -> LexerTable<Kind> {
+        LexerBuilder::new()
+            .rule(Kind::Let, "let")
+            .rule(Kind::Ident, "[A-Za-z_]\\w*")
+            .rule(Kind::Int, "\\d+")
+            .rule(Kind::EqEq, "==")
+            .rule(Kind::Eq, "=")
+            .rule(Kind::Str, "\"[^\"]*\"")
+            .skip("\\s+")
+            .skip("#[^\\n]*")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn longest_match_and_priority() {
+        let lexer = config_lexer();
+        let input = "let letter = \"é\" # comment\nlet==1";
+        let tokens = lexer.lex(input).collect::<Result<Vec<_>, _>>().unwrap();
+        let kinds = tokens.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                Kind::Let,
+                Kind::Ident,
+                Kind::Eq,
+                Kind::Str,
+                Kind::Let,
+                Kind::EqEq,
+                Kind::Int
+            ]
+        );
+        assert_eq!(&input[tokens[1].1.clone()], "letter");
+        assert_eq!(&input[tokens[3].1.clone()], "\"é\"");
+        assert_eq!(tokens[6].1, 33..34);
+    }
+
+    #[test]
+    fn errors_carry_offset() {
+        let lexer = config_lexer();
+        let mut tokens = lexer.lex("let x = @1");
+        assert_eq!(tokens.next(), Some(Ok((Kind::Let, 0..3))));
+        assert_eq!(tokens.next(), Some(Ok((Kind::Ident, 4..5))));
+        assert_eq!(tokens.next(), Some(Ok((Kind::Eq, 6..7))));
+        assert_eq!(tokens.next(), Some(Err(LexError { pos: 8 })));
+        assert_eq!(tokens.next(), None);
+        assert_eq!(
+            LexError { pos: 8 }.to_string(),
+            "unexpected input at byte 8"
+        );
+
+        // An unterminated string is not a token.
+        let errors = lexer
+            .lex("x \"abc")
+            .filter(|t| t.is_err())
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![Err(LexError { pos: 2 })]);
+        assert_eq!(lexer.lex("").count(), 0);
+        assert_eq!(lexer.lex("  # only a comment").count(), 0);
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let cases = [
+            ("a*", "rule matches the empty string"),
+            ("^a", "assertions are not supported by the lexer"),
+            ("a{2,1}", "invalid repetition range"),
+        ];
+        for (pattern, msg) in cases {
+            let e = LexerBuilder::new()
+                .rule(0, "x")
+                .rule(1, pattern)
+                .build()
+                .err();
+            assert_eq!(
+                e.map(|e| (e.rule, e.error.msg)),
+                Some((Some(1), msg)),
+                "{}",
+                pattern
+            );
+        }
+
+        let e = LexerBuilder::<u8>::new()
+            .skip("\\s+")
+            .skip("x?")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "rule 1: rule matches the empty string at position 0"
+        );
+    }
+}
@@ -1,13 +1,54 @@
-use std::ops::Range;
+use nfa::{Context, Nfa};
+use parse::Flags;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use table::{CharClasses, FsmTable, Transitions};
 
+mod backtrack;
+mod builder;
+mod captures;
+mod class;
+mod dot;
+pub mod extended;
+pub mod glob;
+mod lexer;
+pub mod machine;
+mod minimize;
+mod nfa;
+mod ops;
+mod parse;
+mod pikevm;
+pub mod record;
+mod replace;
+mod serialize;
+mod set;
+pub mod statechart;
+mod stream;
+mod table;
 pub mod turnstile;
 
+pub use backtrack::{Backtracker, Timeout, DEFAULT_STEP_BUDGET};
+pub use builder::RegexBuilder;
+pub use captures::{Captures, Matches};
+pub use lexer::{LexError, Lexer, LexerBuilder, LexerTable, RuleError};
+pub use replace::{Replacer, Split, SplitN};
+pub use serialize::{DecodeError, FORMAT_VERSION};
+pub use set::RegexSet;
+pub use stream::Matcher;
+
 type FsmIndex = usize;
 
-const FSM_COLUMN_SIZE: usize = 130;
-const FSM_LINEEND: usize = 129;
+const FSM_ASCII_SIZE: usize = 128;
+
+/// Stands for the accepting state while columns are built, before the final
+/// number of states is known.
+const ACCEPT: FsmIndex = usize::MAX;
+
+/// Largest number of states a pattern may compile into.
+const MAX_STATES: usize = 50_000;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct FsmElement {
     next: FsmIndex,
     offset: usize,
@@ -19,115 +60,423 @@ impl FsmElement {
     }
 }
 
+/// A state with its transition rules. `ts` splits the whole range of Unicode
+/// scalar values into segments: every entry holds the first character of a
+/// segment, which lasts until the start of the next entry. `end` is taken
+/// when the input is exhausted.
 #[derive(Debug, Clone)]
 struct FsmColumn {
-    ts: [FsmElement; FSM_COLUMN_SIZE],
+    ts: Vec<(char, FsmElement)>,
+    end: FsmElement,
 }
 
 impl FsmColumn {
     fn new() -> Self {
         Self {
-            ts: [FsmElement::new(0, 0); FSM_COLUMN_SIZE],
+            ts: vec![('\0', FsmElement::new(0, 0))],
+            end: FsmElement::new(0, 0),
         }
     }
 
-    fn fill_range(&mut self, range: Range<char>, state: FsmIndex, offset: usize) {
-        for i in range {
-            self.ts[i as usize] = FsmElement::new(state, offset);
-        }
+    fn get(&self, c: char) -> FsmElement {
+        let i = self.ts.partition_point(|&(start, _)| start <= c);
+        self.ts[i - 1].1
+    }
+
+    /// Heap bytes held by the transition segments.
+    fn memory_usage(&self) -> usize {
+        self.ts.capacity() * std::mem::size_of::<(char, FsmElement)>()
+    }
+
+    fn elements_mut(&mut self) -> impl Iterator<Item = &mut FsmElement> {
+        self.ts
+            .iter_mut()
+            .map(|(_, t)| t)
+            .chain(std::iter::once(&mut self.end))
+    }
+
+    /// Joins neighbouring segments that share the same transition.
+    fn merge(&mut self) {
+        self.ts.dedup_by(|b, a| a.1 == b.1);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    /// Byte offset in the pattern where the error was detected.
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl CompileError {
+    fn new(pos: usize, msg: &'static str) -> Self {
+        Self { pos, msg }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.msg, self.pos)
     }
 }
 
+impl Error for CompileError {}
+
+/// Bytes used by the two representations of an `Fsm`: the `columns` it was
+/// compiled into and the compressed `table` used for matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub columns: usize,
+    pub table: usize,
+}
+
 pub struct Fsm {
     cs: Vec<FsmColumn>,
+    table: FsmTable,
+    /// The program the automaton was compiled from, used to find where
+    /// matches are and what the groups captured. Automata built out of
+    /// columns have none.
+    nfa: Option<Nfa>,
 }
 
 impl Fsm {
-    pub fn compile(src: &str) -> Self {
-        let mut fsm = Self { cs: Vec::new() };
-        fsm.push(FsmColumn::new()); // Failed State
-        for c in src.chars() {
-            let mut col = FsmColumn::new();
-            match c {
-                '$' => {
-                    col.ts[FSM_LINEEND] = FsmElement::new(fsm.cs.len() + 1, 1);
-                    fsm.push(col);
-                }
-                '.' => {
-                    col.fill_range(32 as char..127 as char, fsm.cs.len() + 1, 1);
-                    fsm.push(col);
-                }
-                '*' => {
-                    let n = fsm.cs.len();
-                    for t in fsm.cs.last_mut().unwrap().ts.iter_mut() {
-                        if t.next == n {
-                            *t = FsmElement::new(n - 1, 1);
-                        } else {
-                            *t = FsmElement::new(n, 0);
-                        }
-                    }
-                }
-                '+' => {
-                    let n = fsm.cs.len();
-                    col = fsm.cs.last().cloned().unwrap();
-                    fsm.push(col);
-                    for t in fsm.cs.last_mut().unwrap().ts.iter_mut() {
-                        if t.next != n {
-                            *t = FsmElement::new(n + 1, 0);
-                        }
-                    }
-                }
-                '?' => {
-                    let n = fsm.cs.len();
-                    for t in fsm.cs.last_mut().unwrap().ts.iter_mut() {
-                        if t.next != n {
-                            *t = FsmElement::new(n, 0);
-                        }
+    /// Compiles `src` with the default options. See `RegexBuilder` to
+    /// change them.
+    pub fn compile(src: &str) -> Result<Self, CompileError> {
+        RegexBuilder::new(src).build()
+    }
+
+    /// Compiles `src` without minimizing, keeping every state found by the
+    /// subset construction.
+    pub fn compile_unminimized(src: &str) -> Result<Self, CompileError> {
+        Self::from_nfa(Nfa::new(&parse::parse(src, Flags::default())?)?)
+    }
+
+    fn from_nfa(nfa: Nfa) -> Result<Self, CompileError> {
+        let mut fsm = Self::from_columns(Self::compile_columns(&nfa)?);
+        fsm.nfa = Some(nfa);
+        Ok(fsm)
+    }
+
+    /// Builds the columns out of the pattern's NFA by subset construction:
+    /// every state stands for the set of NFA instructions the input so far
+    /// may have led to.
+    fn compile_columns(nfa: &Nfa) -> Result<Vec<FsmColumn>, CompileError> {
+        let (segments, reps) = nfa.alphabet();
+        let lines = nfa.has_line_looks();
+
+        // A state is a set of pcs together with what is known about the
+        // character before it, which `^` depends on.
+        let behind = Context {
+            at_start: true,
+            after_newline: false,
+            ..Context::default()
+        };
+        let start = nfa.closure([nfa.start], behind);
+        if nfa.is_match(&start) {
+            return Ok(vec![FsmColumn::new()]);
+        }
+        type Key = (Vec<usize>, Context);
+        let mut sets: Vec<Key> = vec![(Vec::new(), Context::default()), (start, behind)];
+        let mut ids: HashMap<Key, FsmIndex> = sets
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(id, key)| (key, id))
+            .collect();
+        fn id_of(
+            nfa: &Nfa,
+            sets: &mut Vec<Key>,
+            ids: &mut HashMap<Key, FsmIndex>,
+            key: Key,
+        ) -> Result<FsmIndex, CompileError> {
+            if nfa.is_match(&key.0) {
+                return Ok(ACCEPT);
+            }
+            if key.0.is_empty() {
+                return Ok(0);
+            }
+            if let Some(&id) = ids.get(&key) {
+                return Ok(id);
+            }
+            if sets.len() >= MAX_STATES {
+                return Err(CompileError::new(0, "automaton is too large"));
+            }
+            ids.insert(key.clone(), sets.len());
+            sets.push(key);
+            Ok(sets.len() - 1)
+        }
+
+        let mut cs = vec![FsmColumn::new()]; // Failed State
+        while cs.len() < sets.len() {
+            let (set, behind) = sets[cs.len()].clone();
+            let mut targets = Vec::with_capacity(reps.len());
+            for &c in reps.iter() {
+                // Before a `\n` a multi-line `$` holds. When that lets the
+                // set go on, the `\n` is looked at again from there.
+                if c == '\n' && lines {
+                    let ahead = Context {
+                        before_newline: true,
+                        ..behind
+                    };
+                    let set_ahead = nfa.closure(set.iter().copied(), ahead);
+                    if set_ahead != set {
+                        targets.push(FsmElement::new(
+                            id_of(nfa, &mut sets, &mut ids, (set_ahead, behind))?,
+                            0,
+                        ));
+                        continue;
                     }
                 }
-                _ => {
-                    col.ts[c as usize] = FsmElement::new(fsm.cs.len() + 1, 1);
-                    fsm.push(col);
-                }
+                let after = Context {
+                    after_newline: lines && c == '\n',
+                    ..Context::default()
+                };
+                let next = nfa.closure(nfa.step(&set, c), after);
+                targets.push(FsmElement::new(
+                    id_of(nfa, &mut sets, &mut ids, (next, after))?,
+                    1,
+                ));
+            }
+            let mut col = FsmColumn::new();
+            col.ts = segments
+                .iter()
+                .map(|&(start, group)| (start, targets[group]))
+                .collect();
+            let at_end = Context {
+                at_end: true,
+                ..behind
+            };
+            if nfa.is_match(&nfa.closure(set.iter().copied(), at_end)) {
+                col.end = FsmElement::new(ACCEPT, 1);
             }
+            col.merge();
+            cs.push(col);
         }
 
-        fsm
+        let n = cs.len();
+        for col in cs.iter_mut() {
+            for t in col.elements_mut().filter(|t| t.next == ACCEPT) {
+                t.next = n;
+            }
+        }
+        Ok(cs)
+    }
+
+    fn from_columns(cs: Vec<FsmColumn>) -> Self {
+        let table = Self::compress(&cs);
+        Self {
+            cs,
+            table,
+            nfa: None,
+        }
+    }
+
+    /// Resolves the look back transitions taken from `state` on `c`, giving
+    /// the state reached once `c` is consumed.
+    fn resolve(cs: &[FsmColumn], state: FsmIndex, c: char) -> FsmIndex {
+        let mut t = cs[state].get(c);
+        while t.offset == 0 && t.next > 0 && t.next < cs.len() {
+            t = cs[t.next].get(c);
+        }
+        t.next.min(cs.len())
+    }
+
+    /// Whether running out of input in `state` accepts.
+    fn resolve_end(cs: &[FsmColumn], mut state: FsmIndex) -> bool {
+        while state > 0 && state < cs.len() {
+            state = cs[state].end.next;
+        }
+        state >= cs.len()
+    }
+
+    /// The first character of every segment found in any column, so that all
+    /// the characters between two of them behave the same in every state.
+    fn segment_starts(cs: &[FsmColumn]) -> Vec<char> {
+        let mut starts = cs
+            .iter()
+            .flat_map(|col| col.ts.iter().map(|&(start, _)| start))
+            .collect::<Vec<_>>();
+        starts.sort();
+        starts.dedup();
+        starts
+    }
+
+    /// Builds the compressed table: characters are grouped into classes
+    /// which behave the same in every state, and each state keeps one
+    /// transition per class.
+    fn compress(cs: &[FsmColumn]) -> FsmTable {
+        let starts = Self::segment_starts(cs);
+        let mut ids: HashMap<Vec<FsmIndex>, u32> = HashMap::new();
+        let mut classes: Vec<Vec<FsmIndex>> = Vec::new();
+        let mut segments = Vec::with_capacity(starts.len());
+        for &start in starts.iter() {
+            let row = (0..cs.len())
+                .map(|state| Self::resolve(cs, state, start))
+                .collect::<Vec<_>>();
+            let id = *ids.entry(row).or_insert_with_key(|row| {
+                classes.push(row.clone());
+                classes.len() as u32 - 1
+            });
+            segments.push((start, id));
+        }
+
+        let mut cells = Vec::with_capacity(cs.len() * classes.len());
+        for state in 0..cs.len() {
+            cells.extend(classes.iter().map(|row| row[state]));
+        }
+
+        FsmTable {
+            classes: CharClasses::new(&segments, classes.len()),
+            ts: Transitions::new(&cells),
+            eoi: (0..cs.len())
+                .map(|state| Self::resolve_end(cs, state))
+                .collect(),
+            accept: cs.len(),
+        }
+    }
+
+    /// Merges equivalent states with Hopcroft's algorithm and drops the
+    /// unreachable ones. `compile` already does this. Look back transitions
+    /// are resolved on the way, so every transition of the result consumes
+    /// one character.
+    pub fn minimize(&mut self) {
+        let cs = &self.cs;
+        let n = cs.len();
+        if n == 1 {
+            return;
+        }
+        let block_of = minimize::hopcroft(&self.table);
+        let block = |state: FsmIndex| match block_of[state.min(n)] {
+            b if b == block_of[n] => ACCEPT,
+            b => b,
+        };
+        let starts = Self::segment_starts(cs);
+
+        let mut blocks: Vec<Option<FsmColumn>> =
+            vec![None; block_of[..n].iter().max().unwrap() + 1];
+        for state in 1..n {
+            let b = block(state);
+            if b == 0 || b == ACCEPT || blocks[b].is_some() {
+                continue;
+            }
+            let ts = starts
+                .iter()
+                .map(|&c| (c, FsmElement::new(block(Self::resolve(cs, state, c)), 1)))
+                .collect();
+            let end = if Self::resolve_end(cs, state) {
+                FsmElement::new(ACCEPT, 0)
+            } else {
+                FsmElement::new(0, 0)
+            };
+            blocks[b] = Some(FsmColumn { ts, end });
+        }
+
+        // Keep the blocks reachable from the start, in their original order.
+        let mut index = vec![0; blocks.len()];
+        let mut stack = vec![block(1)];
+        while let Some(b) = stack.pop() {
+            if b == 0 || b == ACCEPT || index[b] > 0 {
+                continue;
+            }
+            index[b] = 1;
+            stack.extend(blocks[b].as_ref().unwrap().ts.iter().map(|(_, t)| t.next));
+        }
+        let mut count = 1;
+        for i in index.iter_mut().filter(|i| **i > 0) {
+            *i = count;
+            count += 1;
+        }
+
+        let mut cs = vec![FsmColumn::new()];
+        if block(1) == 0 {
+            cs.push(FsmColumn::new());
+        }
+        for (b, col) in blocks.into_iter().enumerate() {
+            if index[b] == 0 {
+                continue;
+            }
+            let mut col = col.unwrap();
+            for t in col.elements_mut() {
+                t.next = if t.next == ACCEPT {
+                    count
+                } else {
+                    index[t.next]
+                };
+            }
+            col.merge();
+            cs.push(col);
+        }
+        let nfa = self.nfa.take();
+        *self = Self::from_columns(cs);
+        self.nfa = nfa;
     }
 
     pub fn match_str(&self, input: &str) -> bool {
+        let table = &self.table;
         let mut state = 1;
-        let mut ptr = 0;
-        let chars = input.chars().collect::<Vec<_>>();
-        let n = chars.len();
+        for c in input.chars() {
+            if state == 0 || state == table.accept {
+                break;
+            }
+            state = table.next(state, c);
+        }
+        state == table.accept || table.eoi[state]
+    }
 
-        while state > 0 && state < self.cs.len() && ptr < n {
-            let action = &self.cs[state].ts[chars[ptr] as usize];
+    /// Walks the uncompressed columns, following look back transitions one
+    /// at a time. It gives the same answers as `match_str` and is kept as the
+    /// reference to compare the compressed table against.
+    pub fn match_columns(&self, input: &str) -> bool {
+        let mut state = 1;
+        let mut chars = input.chars().peekable();
+
+        while state > 0 && state < self.cs.len() {
+            let Some(&c) = chars.peek() else { break };
+            let action = self.cs[state].get(c);
             state = action.next;
-            ptr = ptr + action.offset;
+            if action.offset > 0 {
+                chars.next();
+            }
         }
 
         if state == 0 {
             return false;
         }
-        if state < self.cs.len() {
-            state = self.cs[state].ts[FSM_LINEEND].next;
-        }
-        return state >= self.cs.len();
+        Self::resolve_end(&self.cs, state)
+    }
+
+    /// Number of states, including the failed state.
+    pub fn state_count(&self) -> usize {
+        self.cs.len()
     }
 
-    fn push(&mut self, column: FsmColumn) {
-        self.cs.push(column);
+    /// Number of alphabet equivalence classes in the compressed table.
+    pub fn class_count(&self) -> usize {
+        self.table.classes.count()
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            columns: self.cs.capacity() * std::mem::size_of::<FsmColumn>()
+                + self.cs.iter().map(|col| col.memory_usage()).sum::<usize>(),
+            table: self.table.memory_usage(),
+        }
     }
 
     pub fn dump(&self) {
-        for symbol in 0..FSM_COLUMN_SIZE {
+        for symbol in 0..FSM_ASCII_SIZE {
             print!("{:03} => ", symbol);
             for column in self.cs.iter() {
-                print!("{:?} ", column.ts[symbol]);
+                print!("{:?} ", column.get(symbol as u8 as char));
             }
             println!();
         }
+        print!("end => ");
+        for column in self.cs.iter() {
+            print!("{:?} ", column.end);
+        }
+        println!();
     }
 }
 
@@ -137,7 +486,7 @@ mod tests {
 
     #[test]
     fn match_any_input() {
-        let fsm = Fsm::compile(".bc$");
+        let fsm = Fsm::compile(".bc$").unwrap();
 
         let inputs = ["Hello, world!", "abc", "dbc", "aaabc"];
         let expects = [false, true, true, false];
@@ -148,7 +497,7 @@ mod tests {
 
     #[test]
     fn match_zero_and_more_times() {
-        let fsm = Fsm::compile("a*bc$");
+        let fsm = Fsm::compile("a*bc$").unwrap();
 
         let inputs = ["Hello, world!", "abc", "bc", "dbc", "aaabc"];
         let expects = [false, true, true, false, true];
@@ -159,7 +508,7 @@ mod tests {
 
     #[test]
     fn match_one_and_more_times() {
-        let fsm = Fsm::compile("a+bc$");
+        let fsm = Fsm::compile("a+bc$").unwrap();
 
         let inputs = ["Hello, world!", "abc", "bc", "dbc", "aaabc"];
         let expects = [false, true, false, false, true];
@@ -170,7 +519,7 @@ mod tests {
 
     #[test]
     fn match_zero_or_one_time() {
-        let fsm = Fsm::compile("a+b?c$");
+        let fsm = Fsm::compile("a+b?c$").unwrap();
 
         let inputs = ["Hello, world!", "abc", "bc", "ac", "aaabc"];
         let expects = [false, true, false, true, true];
@@ -178,4 +527,287 @@ mod tests {
             assert_eq!(fsm.match_str(input), expects[i]);
         }
     }
+
+    #[test]
+    fn match_multibyte_input() {
+        let fsm = Fsm::compile("é.中$").unwrap();
+
+        let inputs = ["é中", "éa中", "é😀中", "e😀中", "é\n中"];
+        let expects = [false, true, true, false, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+    }
+
+    #[test]
+    fn match_bracket_expression() {
+        let fsm = Fsm::compile("[a-cé-ë]+[^0-9]$").unwrap();
+
+        let inputs = ["ab!", "aêb中", "d!", "ab1", "ëëë😀"];
+        let expects = [true, true, false, false, true];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+    }
+
+    #[test]
+    fn match_unicode_classes() {
+        let fsm = Fsm::compile("[\\p{Alphabetic}_]+\\s\\d$").unwrap();
+
+        let inputs = ["日本語 7", "naïve_x\u{3000}0", "abc\t", "123 4"];
+        let expects = [true, true, false, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+    }
+
+    #[test]
+    fn compile_errors() {
+        let patterns = ["*a", "[abc", "a\\", "\\p{Bogus}", "[z-a]"];
+        let positions = [0, 0, 1, 0, 0];
+        for (i, &pattern) in patterns.iter().enumerate() {
+            assert_eq!(
+                Fsm::compile(pattern).err().map(|e| e.pos),
+                Some(positions[i])
+            );
+        }
+    }
+
+    #[test]
+    fn table_agrees_with_columns() {
+        let patterns = [
+            "a*bc$",
+            "a+b?c$",
+            ".bc$",
+            "[a-c]+[^0-9]$",
+            "x[é-ë]*\\d",
+            "ab*$",
+        ];
+        let inputs = [
+            "", "abc", "bc", "aaabc", "aêb中", "xëé7", "x9", "ab", "abbbb", "a1",
+        ];
+        for &pattern in patterns.iter() {
+            let fsm = Fsm::compile(pattern).unwrap();
+            for &input in inputs.iter() {
+                assert_eq!(fsm.match_str(input), fsm.match_columns(input));
+            }
+        }
+    }
+
+    #[test]
+    fn table_uses_alphabet_classes() {
+        let fsm = Fsm::compile("[a-c][x-z]$").unwrap();
+        // `a-c`, `x-z` and everything else.
+        assert_eq!(fsm.class_count(), 3);
+        assert!(matches!(fsm.table.ts, Transitions::U8(_)));
+
+        let fsm = Fsm::compile(&"[a-z]+[0-9]*".repeat(20)).unwrap();
+        let usage = fsm.memory_usage();
+        assert!(usage.table < usage.columns);
+
+        let fsm = Fsm::compile(&"a".repeat(300)).unwrap();
+        assert!(matches!(fsm.table.ts, Transitions::U16(_)));
+        assert!(fsm.match_str(&"a".repeat(300)));
+        assert!(!fsm.match_str(&"a".repeat(299)));
+    }
+
+    #[test]
+    fn minimize_equivalent_patterns() {
+        let inputs = ["", "a", "aa", "aaab", "b", "ba", "x", "xyz", "aaaaaa$"];
+        let pairs = [
+            ("aa*", "a+"),
+            ("a*a*", "a*"),
+            ("a+a*$", "a+$"),
+            ("[ab]c|", "[ba]c|"),
+        ];
+        for (p, q) in pairs {
+            let p = Fsm::compile(p).unwrap();
+            let q = Fsm::compile(q).unwrap();
+            assert_eq!(p.state_count(), q.state_count());
+            for &input in inputs.iter() {
+                assert_eq!(p.match_str(input), q.match_str(input));
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_keeps_behaviour() {
+        let patterns = [
+            "a*bc$",
+            "a+b?c$",
+            "a*a*b*$",
+            "[a-c]*[a-c]*x",
+            "[^x]*",
+            "x?y?z?$",
+        ];
+        let inputs = [
+            "", "abc", "bc", "aaabc", "abbb", "ccbax", "xyz", "xz", "y", "zz",
+        ];
+        for &pattern in patterns.iter() {
+            let full = Fsm::compile_unminimized(pattern).unwrap();
+            let min = Fsm::compile(pattern).unwrap();
+            assert!(min.state_count() <= full.state_count());
+            for &input in inputs.iter() {
+                assert_eq!(min.match_str(input), full.match_str(input));
+                assert_eq!(min.match_columns(input), full.match_str(input));
+            }
+        }
+
+        assert_eq!(Fsm::compile("a*a*b*$").unwrap().state_count(), 3);
+        // Accepts every input, so only the failed state is left.
+        assert_eq!(Fsm::compile("[^x]*").unwrap().state_count(), 1);
+    }
+
+    #[test]
+    fn match_counted_repetition() {
+        let fsm = Fsm::compile("0x[0-9a-f]{4}$").unwrap();
+
+        let inputs = ["0x1f2e", "0x1f2", "0x1f2e3", "0xabcg"];
+        let expects = [true, false, false, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+
+        let fsm = Fsm::compile("a{2,}b{1,3}$").unwrap();
+
+        let inputs = ["aab", "ab", "aaaabbb", "aabbbb", "aa"];
+        let expects = [true, false, true, false, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+    }
+
+    #[test]
+    fn match_repetition_edge_cases() {
+        let patterns = ["a{0}b$", "a{0,1}b$", "a{1}$", "a{0,}b$", "x{,2}$", "x{a}$"];
+        let inputs = ["b", "ab", "aab", "a", "x{,2}", "x{a}"];
+        let expects = [
+            [true, false, false, false, false, false],
+            [true, true, false, false, false, false],
+            [false, false, false, true, false, false],
+            [true, true, true, false, false, false],
+            [false, false, false, false, true, false],
+            [false, false, false, false, false, true],
+        ];
+        for (i, &pattern) in patterns.iter().enumerate() {
+            let fsm = Fsm::compile(pattern).unwrap();
+            for (j, &input) in inputs.iter().enumerate() {
+                assert_eq!(fsm.match_str(input), expects[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn match_groups() {
+        let fsm = Fsm::compile("(ab){2,3}c$").unwrap();
+
+        let inputs = ["abc", "ababc", "abababc", "ababababc", "abac"];
+        let expects = [false, true, true, false, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+
+        let fsm = Fsm::compile("(a[0-9]*)+a$").unwrap();
+
+        let inputs = ["a", "aa", "a12a", "a1a2a", "a1a2"];
+        let expects = [false, true, true, true, false];
+        for (i, &input) in inputs.iter().enumerate() {
+            assert_eq!(fsm.match_str(input), expects[i]);
+        }
+    }
+
+    #[test]
+    fn repetition_errors() {
+        let patterns = ["a{1001}", "a{3,2}", "{2}", "(a{1000}){1000}", "(ab", "ab)"];
+        let messages = [
+            "repetition bound exceeds 1000",
+            "invalid repetition range",
+            "quantifier without a preceding atom",
+            "pattern is too large",
+            "unclosed group",
+            "unmatched closing parenthesis",
+        ];
+        for (i, &pattern) in patterns.iter().enumerate() {
+            assert_eq!(
+                Fsm::compile(pattern).err().map(|e| e.msg),
+                Some(messages[i])
+            );
+        }
+    }
+
+    #[test]
+    fn match_case_insensitive() {
+        let fsm = Fsm::compile("(?i)rust$").unwrap();
+        assert!(fsm.match_str("rUsT"));
+        assert!(fsm.match_str("RUST"));
+        assert!(!fsm.match_str("rost"));
+
+        assert!(Fsm::compile("(?i)é$").unwrap().match_str("É"));
+        assert!(Fsm::compile("(?i)k$").unwrap().match_str("\u{212A}"));
+        assert!(!Fsm::compile("(?i)[^a]").unwrap().match_str("A"));
+        assert!(Fsm::compile("(?i)[a-c]+$").unwrap().match_str("aBc"));
+
+        let scoped = Fsm::compile("(?i:a)b$").unwrap();
+        assert!(scoped.match_str("Ab"));
+        assert!(!scoped.match_str("AB"));
+        let off = Fsm::compile("(?i)a(?-i)b$").unwrap();
+        assert!(off.match_str("Ab"));
+        assert!(!off.match_str("AB"));
+    }
+
+    #[test]
+    fn match_multi_line() {
+        for pattern in ["a$\\nb", "a\\n^b$"] {
+            assert!(
+                !Fsm::compile(pattern).unwrap().match_str("a\nb"),
+                "{}",
+                pattern
+            );
+        }
+        for pattern in ["(?m)a$\\nb", "(?m)a\\n^b$", "(?m)a$\\n^b$", "(?m)a$"] {
+            assert!(
+                Fsm::compile(pattern).unwrap().match_str("a\nb"),
+                "{}",
+                pattern
+            );
+        }
+        let fsm = Fsm::compile("(?m)(x*$\\n)*end$").unwrap();
+        assert!(fsm.match_str("xx\n\nx\nend"));
+        assert!(!fsm.match_str("xx\nyx\nend"));
+        assert!(!Fsm::compile("(?m)a$").unwrap().match_str("ab\n"));
+    }
+
+    #[test]
+    fn match_dot_all() {
+        assert!(!Fsm::compile("a.b").unwrap().match_str("a\nb"));
+        assert!(Fsm::compile("(?s)a.b").unwrap().match_str("a\nb"));
+        assert!(Fsm::compile("(?s:.)\\n.").unwrap().match_str("\n\nx"));
+    }
+
+    #[test]
+    fn builder_options() {
+        let fsm = RegexBuilder::new("a.^b$")
+            .case_insensitive(true)
+            .multi_line(true)
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap();
+        assert!(fsm.match_str("A\nB"));
+        let fsm = RegexBuilder::new("a.^b$")
+            .case_insensitive(true)
+            .multi_line(true)
+            .build()
+            .unwrap();
+        assert!(!fsm.match_str("A\nB"));
+        let fsm = RegexBuilder::new("(?-i)a")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(!fsm.match_str("A"));
+
+        assert_eq!(
+            Fsm::compile("(?x)a").err().map(|e| (e.pos, e.msg)),
+            Some((2, "unknown flag"))
+        );
+    }
 }
@@ -0,0 +1,220 @@
+//! State machines with a finite set of states and events, declared with
+//! `state_machine!`.
+
+/// A machine moving between states of type `S` on events of type `E`.
+pub trait StateMachine<S: Copy + 'static, E: Copy + 'static> {
+    /// Every state, in declaration order.
+    const STATES: &'static [S];
+    /// Every event, in declaration order.
+    const EVENTS: &'static [E];
+    const INITIAL: S;
+
+    fn next_state(state: S, event: E) -> S;
+
+    /// The state reached from `INITIAL` after `events`.
+    fn run(events: impl IntoIterator<Item = E>) -> S {
+        events
+            .into_iter()
+            .fold(Self::INITIAL, |state, event| Self::next_state(state, event))
+    }
+}
+
+/// Declares a state machine from its transitions, written
+/// `State + Event => State`. It generates:
+///
+/// - the state and event enums, with `Display` showing the variant names;
+/// - a unit struct for the machine implementing `StateMachine`;
+/// - the transition table as a constant.
+///
+/// Every state must have exactly one transition for every event. A missing
+/// or duplicate transition fails to compile.
+///
+/// ```
+/// use regexp::machine::StateMachine;
+/// use regexp::state_machine;
+///
+/// state_machine! {
+///     pub machine Door {
+///         states DoorState { Closed, Open }
+///         events DoorEvent { Pull, Shut }
+///         initial Closed;
+///
+///         Closed + Pull => Open,
+///         Closed + Shut => Closed,
+///         Open + Pull => Open,
+///         Open + Shut => Closed,
+///     }
+/// }
+///
+/// assert_eq!(Door::run([DoorEvent::Pull]), DoorState::Open);
+/// assert_eq!(DoorState::Open.to_string(), "Open");
+/// ```
+///
+/// Forgetting `Open + Shut` does not compile:
+///
+/// ```compile_fail
+/// regexp::state_machine! {
+///     machine Door {
+///         states DoorState { Closed, Open }
+///         events DoorEvent { Pull, Shut }
+///         initial Closed;
+///
+///         Closed + Pull => Open,
+///         Closed + Shut => Closed,
+///         Open + Pull => Open,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! state_machine {
+    (
+        $(#[$meta:meta])*
+        $vis:vis machine $machine:ident {
+            states $state:ident { $($s:ident),+ $(,)? }
+            events $event:ident { $($e:ident),+ $(,)? }
+            initial $initial:ident;
+
+            $($from:ident + $on:ident => $to:ident),+ $(,)?
+        }
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $state {
+            $($s),+
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $event {
+            $($e),+
+        }
+
+        impl ::std::fmt::Display for $state {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(match self {
+                    $($state::$s => stringify!($s)),+
+                })
+            }
+        }
+
+        impl ::std::fmt::Display for $event {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(match self {
+                    $($event::$e => stringify!($e)),+
+                })
+            }
+        }
+
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis struct $machine;
+
+        impl $machine {
+            const STATE_COUNT: usize = [$($state::$s),+].len();
+            const EVENT_COUNT: usize = [$($event::$e),+].len();
+
+            /// Next state of every state on every event. Building it checks
+            /// that there is exactly one transition for each pair.
+            const TABLE: [[$state; Self::EVENT_COUNT]; Self::STATE_COUNT] = {
+                let transitions = [$(($state::$from, $event::$on, $state::$to)),+];
+                let mut table = [[None; Self::EVENT_COUNT]; Self::STATE_COUNT];
+                let mut i = 0;
+                while i < transitions.len() {
+                    let (from, on, to) = transitions[i];
+                    if table[from as usize][on as usize].is_some() {
+                        panic!(concat!(
+                            "state machine `",
+                            stringify!($machine),
+                            "` has two transitions for the same state and event"
+                        ));
+                    }
+                    table[from as usize][on as usize] = Some(to);
+                    i += 1;
+                }
+
+                let mut result = [[$state::$initial; Self::EVENT_COUNT]; Self::STATE_COUNT];
+                let mut s = 0;
+                while s < Self::STATE_COUNT {
+                    let mut e = 0;
+                    while e < Self::EVENT_COUNT {
+                        result[s][e] = match table[s][e] {
+                            Some(to) => to,
+                            None => panic!(concat!(
+                                "state machine `",
+                                stringify!($machine),
+                                "` is missing a transition"
+                            )),
+                        };
+                        e += 1;
+                    }
+                    s += 1;
+                }
+                result
+            };
+        }
+
+        // Evaluated even if the machine is never run.
+        const _: () = {
+            let _ = $machine::TABLE;
+        };
+
+        impl $crate::machine::StateMachine<$state, $event> for $machine {
+            const STATES: &'static [$state] = &[$($state::$s),+];
+            const EVENTS: &'static [$event] = &[$($event::$e),+];
+            const INITIAL: $state = $state::$initial;
+
+            fn next_state(state: $state, event: $event) -> $state {
+                Self::TABLE[state as usize][event as usize]
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    state_machine! {
+        /// A connection which has to be opened before sending.
+        machine Connection {
+            states Conn { Closed, Opening, Open }
+            events Signal { Connect, Ack, Send, Close }
+            initial Closed;
+
+            Closed + Connect => Opening,
+            Closed + Ack => Closed,
+            Closed + Send => Closed,
+            Closed + Close => Closed,
+            Opening + Connect => Opening,
+            Opening + Ack => Open,
+            Opening + Send => Closed,
+            Opening + Close => Closed,
+            Open + Connect => Open,
+            Open + Ack => Open,
+            Open + Send => Open,
+            Open + Close => Closed,
+        }
+    }
+
+    #[test]
+    fn generated_machine() {
+        assert_eq!(
+            Connection::STATES,
+            &[Conn::Closed, Conn::Opening, Conn::Open]
+        );
+        assert_eq!(Connection::EVENTS.len(), 4);
+        assert_eq!(Connection::INITIAL, Conn::Closed);
+        assert_eq!(
+            Connection::next_state(Conn::Opening, Signal::Ack),
+            Conn::Open
+        );
+        assert_eq!(
+            Connection::run([Signal::Connect, Signal::Ack, Signal::Send]),
+            Conn::Open
+        );
+        assert_eq!(
+            Connection::run([Signal::Connect, Signal::Send, Signal::Ack]),
+            Conn::Closed
+        );
+        assert_eq!(Conn::Opening.to_string(), "Opening");
+        assert_eq!(Signal::Close.to_string(), "Close");
+    }
+}
@@ -0,0 +1,101 @@
+use crate::table::FsmTable;
+
+/// Hopcroft's partition refinement over the compressed table. The accepting
+/// state `table.accept` takes part as a state of its own which loops on
+/// every class. Returns the block of every state in `0..=table.accept`,
+/// numbered in order of their smallest member, so the failed state is always
+/// in block `0`.
+pub fn hopcroft(table: &FsmTable) -> Vec<usize> {
+    let n = table.accept + 1;
+    let k = table.classes.count();
+    let next = |state: usize, class: usize| {
+        if state == table.accept {
+            state
+        } else {
+            table.ts.get(state * k + class)
+        }
+    };
+
+    let mut preds = vec![vec![Vec::new(); n]; k];
+    for state in 0..n {
+        for (class, preds) in preds.iter_mut().enumerate() {
+            preds[next(state, class)].push(state);
+        }
+    }
+
+    // States accepting the rest of the input when it is empty, and the others.
+    let mut blocks: Vec<Vec<usize>> = vec![Vec::new(); 2];
+    for state in 0..n {
+        let accepting = state == table.accept || table.eoi[state];
+        blocks[accepting as usize].push(state);
+    }
+    blocks.retain(|block| !block.is_empty());
+    let mut block_of = vec![0; n];
+    for (b, block) in blocks.iter().enumerate() {
+        for &state in block.iter() {
+            block_of[state] = b;
+        }
+    }
+
+    let mut work = (0..blocks.len()).collect::<Vec<_>>();
+    let mut marked = vec![false; n];
+    while let Some(a) = work.pop() {
+        let splitter = blocks[a].clone();
+        for preds in preds.iter() {
+            let mut touched = Vec::new();
+            for &target in splitter.iter() {
+                for &state in preds[target].iter() {
+                    if !marked[state] {
+                        marked[state] = true;
+                        touched.push(block_of[state]);
+                    }
+                }
+            }
+            touched.sort();
+            touched.dedup();
+
+            for y in touched {
+                let (inside, outside): (Vec<_>, Vec<_>) =
+                    blocks[y].iter().partition(|&&state| marked[state]);
+                if outside.is_empty() {
+                    continue;
+                }
+                // The smaller half becomes the new block, which always goes
+                // to the worklist. If `y` is still waiting there, both halves
+                // now are; otherwise the smaller one is enough to split the
+                // others, so `y` never needs to be added back.
+                let z = blocks.len();
+                let (keep, moved) = if inside.len() <= outside.len() {
+                    (outside, inside)
+                } else {
+                    (inside, outside)
+                };
+                for &state in moved.iter() {
+                    block_of[state] = z;
+                }
+                blocks[y] = keep;
+                blocks.push(moved);
+                work.push(z);
+            }
+
+            for &target in splitter.iter() {
+                for &state in preds[target].iter() {
+                    marked[state] = false;
+                }
+            }
+        }
+    }
+
+    let mut labels = vec![usize::MAX; blocks.len()];
+    let mut count = 0;
+    (0..n)
+        .map(|state| {
+            let label = &mut labels[block_of[state]];
+            if *label == usize::MAX {
+                *label = count;
+                count += 1;
+            }
+            *label
+        })
+        .collect()
+}
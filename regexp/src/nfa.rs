@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use crate::class::{next_char, CharClass};
+use crate::parse::{Ast, Look};
+use crate::CompileError;
+
+/// Largest number of instructions a pattern may compile into.
+const MAX_INSTS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub enum Inst {
+    /// Consumes a character of the class and goes on at the given pc.
+    Class(CharClass, usize),
+    /// Goes on at both pcs, preferring the first one.
+    Split(usize, usize),
+    /// Goes on only where the assertion holds.
+    Look(Look, usize),
+    /// Records the current position in a capture slot and goes on.
+    Save(usize, usize),
+    Match,
+}
+
+/// What is known about the surroundings of the current position, to decide
+/// which assertions hold there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Context {
+    pub at_start: bool,
+    pub after_newline: bool,
+    pub at_end: bool,
+    pub before_newline: bool,
+}
+
+impl Context {
+    /// The surroundings of byte offset `pos` in `text`.
+    pub fn at(text: &str, pos: usize) -> Self {
+        Self {
+            at_start: pos == 0,
+            after_newline: text[..pos].ends_with('\n'),
+            at_end: pos == text.len(),
+            before_newline: text[pos..].starts_with('\n'),
+        }
+    }
+
+    pub fn holds(&self, look: Look) -> bool {
+        match look {
+            Look::StartText => self.at_start,
+            Look::EndText => self.at_end,
+            Look::StartLine => self.at_start || self.after_newline,
+            Look::EndLine => self.at_end || self.before_newline,
+        }
+    }
+}
+
+/// A Thompson NFA: a program of `Inst`s starting at `start`.
+///
+/// Group `i` is saved in slots `2 * i` and `2 * i + 1`, group `0` being the
+/// whole match. `names` holds the name of every group.
+#[derive(Debug, Clone)]
+pub struct Nfa {
+    pub insts: Vec<Inst>,
+    pub start: usize,
+    pub names: Vec<Option<String>>,
+}
+
+impl Nfa {
+    pub fn new(ast: &Ast) -> Result<Self, CompileError> {
+        let mut nfa = Self {
+            insts: vec![Inst::Match],
+            start: 0,
+            names: vec![None],
+        };
+        let end = nfa.push(Inst::Save(1, 0))?;
+        let body = nfa.emit(ast, end)?;
+        nfa.start = nfa.push(Inst::Save(0, body))?;
+        Ok(nfa)
+    }
+
+    /// Number of capture slots.
+    pub fn slots(&self) -> usize {
+        self.names.len() * 2
+    }
+
+    fn push(&mut self, inst: Inst) -> Result<usize, CompileError> {
+        if self.insts.len() >= MAX_INSTS {
+            return Err(CompileError::new(0, "pattern is too large"));
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    /// Emits the instructions of `ast`, which continue at `next` once it has
+    /// matched, and returns the pc to enter them.
+    fn emit(&mut self, ast: &Ast, next: usize) -> Result<usize, CompileError> {
+        match ast {
+            Ast::Empty => Ok(next),
+            Ast::Class(class) => self.push(Inst::Class(class.clone(), next)),
+            Ast::Look(look) => self.push(Inst::Look(*look, next)),
+            Ast::Concat(items) => {
+                let mut next = next;
+                for item in items.iter().rev() {
+                    next = self.emit(item, next)?;
+                }
+                Ok(next)
+            }
+            Ast::Group(ast) => self.emit(ast, next),
+            Ast::Alternate(alternatives) => {
+                let mut entries = Vec::with_capacity(alternatives.len());
+                for ast in alternatives.iter() {
+                    entries.push(self.emit(ast, next)?);
+                }
+                let mut entry = match entries.pop() {
+                    Some(entry) => entry,
+                    None => return Ok(next),
+                };
+                while let Some(first) = entries.pop() {
+                    entry = self.push(Inst::Split(first, entry))?;
+                }
+                Ok(entry)
+            }
+            &Ast::BackRef { pos, .. } => Err(CompileError::new(
+                pos,
+                "backreferences need the backtracking engine",
+            )),
+            &Ast::LookAround { pos, .. } => Err(CompileError::new(
+                pos,
+                "look-around needs the backtracking engine",
+            )),
+            Ast::Capture { index, name, ast } => {
+                if self.names.len() <= *index {
+                    self.names.resize(index + 1, None);
+                }
+                self.names[*index] = name.clone();
+                let end = self.push(Inst::Save(index * 2 + 1, next))?;
+                let body = self.emit(ast, end)?;
+                self.push(Inst::Save(index * 2, body))
+            }
+            Ast::Repeat {
+                ast,
+                min,
+                max,
+                greedy,
+            } => {
+                // Greedy repetitions try the body first, lazy ones skip it.
+                let split_of = |body, skip| {
+                    if *greedy {
+                        Inst::Split(body, skip)
+                    } else {
+                        Inst::Split(skip, body)
+                    }
+                };
+                let mut next = match max {
+                    // Up to `max - min` optional copies: `x{0,2}` is `(x(x)?)?`.
+                    Some(max) => {
+                        let done = next;
+                        let mut next = next;
+                        for _ in *min..*max {
+                            let split = self.push(Inst::Split(0, 0))?;
+                            let body = self.emit(ast, next)?;
+                            self.insts[split] = split_of(body, done);
+                            next = split;
+                        }
+                        next
+                    }
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        let body = self.emit(ast, split)?;
+                        self.insts[split] = split_of(body, next);
+                        split
+                    }
+                };
+                for _ in 0..*min {
+                    next = self.emit(ast, next)?;
+                }
+                Ok(next)
+            }
+        }
+    }
+
+    /// Splits the characters into segments which every `Class` instruction
+    /// either contains or misses as a whole. Segments contained in the same
+    /// instructions form a group. Returns the start of every segment with its
+    /// group, and one character of each group.
+    pub fn alphabet(&self) -> (Vec<(char, usize)>, Vec<char>) {
+        let classes = self
+            .insts
+            .iter()
+            .enumerate()
+            .filter_map(|(pc, inst)| match inst {
+                Inst::Class(class, _) => Some((pc, class)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Line assertions need `\n` in a group of its own.
+        let lines = self.has_line_looks();
+        let mut starts = vec!['\0'];
+        if lines {
+            starts.push('\n');
+            starts.extend(next_char('\n'));
+        }
+        for (_, class) in classes.iter() {
+            for range in class.ranges() {
+                starts.push(*range.start());
+                starts.extend(next_char(*range.end()));
+            }
+        }
+        starts.sort();
+        starts.dedup();
+
+        let mut groups: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut reps = Vec::new();
+        let segments = starts
+            .into_iter()
+            .map(|start| {
+                let mut members = classes
+                    .iter()
+                    .filter(|(_, class)| class.contains(start))
+                    .map(|&(pc, _)| pc)
+                    .collect::<Vec<_>>();
+                if lines && start == '\n' {
+                    members.push(usize::MAX);
+                }
+                let group = *groups.entry(members).or_insert_with(|| {
+                    reps.push(start);
+                    reps.len() - 1
+                });
+                (start, group)
+            })
+            .collect();
+        (segments, reps)
+    }
+
+    /// Whether the program uses `^` or `$` in multi-line mode.
+    pub fn has_line_looks(&self) -> bool {
+        self.insts
+            .iter()
+            .any(|inst| matches!(inst, Inst::Look(Look::StartLine | Look::EndLine, _)))
+    }
+
+    /// Follows the instructions which do not consume input from `pcs`,
+    /// collecting the sorted `Class`, `Look` and `Match` pcs reached. A
+    /// `Look` is passed through when its assertion holds in `ctx`, and kept
+    /// otherwise.
+    pub fn closure(&self, pcs: impl IntoIterator<Item = usize>, ctx: Context) -> Vec<usize> {
+        let mut seen = vec![false; self.insts.len()];
+        let mut stack = pcs.into_iter().collect::<Vec<_>>();
+        let mut set = Vec::new();
+        while let Some(pc) = stack.pop() {
+            if seen[pc] {
+                continue;
+            }
+            seen[pc] = true;
+            match self.insts[pc] {
+                Inst::Split(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Look(look, next) if ctx.holds(look) => stack.push(next),
+                Inst::Save(_, next) => stack.push(next),
+                _ => set.push(pc),
+            }
+        }
+        set.sort();
+        set
+    }
+
+    /// The pcs reached from `set` by consuming `c`, before their closure.
+    pub fn step(&self, set: &[usize], c: char) -> Vec<usize> {
+        set.iter()
+            .filter_map(|&pc| match &self.insts[pc] {
+                Inst::Class(class, next) if class.contains(c) => Some(*next),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn is_match(&self, set: &[usize]) -> bool {
+        set.iter().any(|&pc| matches!(self.insts[pc], Inst::Match))
+    }
+}
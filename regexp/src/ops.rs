@@ -0,0 +1,286 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use crate::class::prev_char;
+use crate::{Fsm, FsmColumn, FsmElement, FsmIndex, ACCEPT};
+
+/// What becomes of the input once an automaton built by `explore` is in
+/// some state.
+enum Kind {
+    /// Rejected whatever comes next.
+    Fail,
+    /// Accepted whatever comes next.
+    Accept,
+    /// Depends on what comes next. `end` tells whether running out of input
+    /// accepts.
+    Live { end: bool },
+}
+
+impl Fsm {
+    /// Accepts what `self` or `other` accepts.
+    pub fn union(&self, other: &Fsm) -> Fsm {
+        self.product(other, |a, b| a || b)
+    }
+
+    /// Accepts what both `self` and `other` accept. Two patterns never match
+    /// the same input when their intersection `is_empty`.
+    pub fn intersection(&self, other: &Fsm) -> Fsm {
+        self.product(other, |a, b| a && b)
+    }
+
+    /// Accepts what `self` rejects.
+    pub fn complement(&self) -> Fsm {
+        let cs = &self.cs;
+        let n = cs.len();
+        Self::explore(
+            &Self::segment_starts(cs),
+            1,
+            |&state, c| Self::step(cs, state, c),
+            |&state| match state {
+                0 => Kind::Accept,
+                s if s >= n => Kind::Fail,
+                s => Kind::Live {
+                    end: !Self::resolve_end(cs, s),
+                },
+            },
+        )
+    }
+
+    /// Whether no input at all is accepted.
+    pub fn is_empty(&self) -> bool {
+        self.shortest_accepted_string().is_none()
+    }
+
+    /// One of the shortest inputs accepted, found by a breadth-first search
+    /// from the start state. Letters and other printable ASCII characters
+    /// are preferred when a transition allows a choice.
+    pub fn shortest_accepted_string(&self) -> Option<String> {
+        let cs = &self.cs;
+        let n = cs.len();
+        let starts = Self::segment_starts(cs);
+        let reps = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts
+                    .get(i + 1)
+                    .map_or(char::MAX, |&next| prev_char(next).unwrap());
+                witness_char(start, end)
+            })
+            .collect::<Vec<_>>();
+
+        // Where every state was first reached from, and on which character.
+        let mut from: Vec<Option<(FsmIndex, char)>> = vec![None; n + 1];
+        let mut seen = vec![false; n + 1];
+        let mut queue = VecDeque::from([1]);
+        seen[1] = true;
+        while let Some(state) = queue.pop_front() {
+            if state >= n || Self::resolve_end(cs, state) {
+                let mut witness = Vec::new();
+                let mut state = state;
+                while let Some((prev, c)) = from[state] {
+                    witness.push(c);
+                    state = prev;
+                }
+                return Some(witness.into_iter().rev().collect());
+            }
+            for &c in reps.iter() {
+                let next = Self::step(cs, state, c);
+                if next != 0 && !seen[next] {
+                    seen[next] = true;
+                    from[next] = Some((state, c));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The state reached from `state` on `c`, the failed and accepting
+    /// states keeping themselves.
+    fn step(cs: &[FsmColumn], state: FsmIndex, c: char) -> FsmIndex {
+        if state == 0 || state >= cs.len() {
+            state.min(cs.len())
+        } else {
+            Self::resolve(cs, state, c)
+        }
+    }
+
+    /// Runs `self` and `other` side by side. `op` combines whether each of
+    /// them accepts, and is used both for what comes next and for the end of
+    /// input.
+    fn product(&self, other: &Fsm, op: impl Fn(bool, bool) -> bool) -> Fsm {
+        let (a, b) = (&self.cs, &other.cs);
+        let mut starts = Self::segment_starts(a);
+        starts.extend(Self::segment_starts(b));
+        starts.sort();
+        starts.dedup();
+        Self::explore(
+            &starts,
+            (1, 1),
+            |&(x, y), c| (Self::step(a, x, c), Self::step(b, y, c)),
+            |&(x, y)| {
+                if !op(x > 0, y > 0) {
+                    Kind::Fail
+                } else if op(x >= a.len(), y >= b.len()) {
+                    Kind::Accept
+                } else {
+                    Kind::Live {
+                        end: op(Self::resolve_end(a, x), Self::resolve_end(b, y)),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Builds the minimal automaton of the states reachable from `start`.
+    /// `starts` are the segments of characters which `next` tells apart.
+    fn explore<K: Clone + Eq + Hash>(
+        starts: &[char],
+        start: K,
+        next: impl Fn(&K, char) -> K,
+        kind: impl Fn(&K) -> Kind,
+    ) -> Fsm {
+        let mut cs = vec![FsmColumn::new()]; // Failed State
+        match kind(&start) {
+            Kind::Fail => cs.push(FsmColumn::new()),
+            Kind::Accept => {}
+            Kind::Live { .. } => {
+                let mut keys = vec![start.clone()];
+                let mut ids = HashMap::from([(start, 1)]);
+                while cs.len() <= keys.len() {
+                    let key = keys[cs.len() - 1].clone();
+                    let mut col = FsmColumn::new();
+                    col.ts = starts
+                        .iter()
+                        .map(|&c| {
+                            let to = next(&key, c);
+                            let id = match kind(&to) {
+                                Kind::Fail => 0,
+                                Kind::Accept => ACCEPT,
+                                Kind::Live { .. } => *ids.entry(to.clone()).or_insert_with(|| {
+                                    keys.push(to);
+                                    keys.len()
+                                }),
+                            };
+                            (c, FsmElement::new(id, 1))
+                        })
+                        .collect();
+                    if let Kind::Live { end: true } = kind(&key) {
+                        col.end = FsmElement::new(ACCEPT, 1);
+                    }
+                    col.merge();
+                    cs.push(col);
+                }
+            }
+        }
+
+        let n = cs.len();
+        for col in cs.iter_mut() {
+            for t in col.elements_mut().filter(|t| t.next == ACCEPT) {
+                t.next = n;
+            }
+        }
+        let mut fsm = Fsm::from_columns(cs);
+        fsm.minimize();
+        fsm
+    }
+}
+
+/// A character of `start..=end` to show in a witness: a lowercase letter,
+/// digit or other printable ASCII character if there is one.
+fn witness_char(start: char, end: char) -> char {
+    ['a'..='z', '0'..='9', '!'..='~']
+        .into_iter()
+        .find_map(|preferred| {
+            let c = start.max(*preferred.start());
+            (c <= end && c <= *preferred.end()).then_some(c)
+        })
+        .unwrap_or(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: [&str; 12] = [
+        "", "a", "b", "ab", "aab", "ba", "abc", "é", "x1", "hij", "abcabc", "zz",
+    ];
+
+    #[test]
+    fn combine_like_match_str() {
+        let patterns = ["a+$", "[a-c]*b", "ab", "", "\\w\\d$", "[h-z]+$", "é?$"];
+        for p in patterns {
+            let a = Fsm::compile(p).unwrap();
+            let complement = a.complement();
+            for input in INPUTS {
+                assert_eq!(complement.match_str(input), !a.match_str(input), "!{}", p);
+            }
+            for q in patterns {
+                let b = Fsm::compile(q).unwrap();
+                let union = a.union(&b);
+                let intersection = a.intersection(&b);
+                for input in INPUTS {
+                    let (x, y) = (a.match_str(input), b.match_str(input));
+                    assert_eq!(union.match_str(input), x || y, "{} | {}", p, q);
+                    assert_eq!(intersection.match_str(input), x && y, "{} & {}", p, q);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_rules() {
+        let api = Fsm::compile("/api/\\w+$").unwrap();
+        let assets = Fsm::compile("/static/\\w+$").unwrap();
+        assert!(api.intersection(&assets).is_empty());
+
+        let versioned = Fsm::compile("/\\w+/v2$").unwrap();
+        let overlap = api.intersection(&versioned);
+        assert!(!overlap.is_empty());
+        let witness = overlap.shortest_accepted_string().unwrap();
+        assert_eq!(witness, "/api/v2");
+        assert!(api.match_str(&witness) && versioned.match_str(&witness));
+    }
+
+    #[test]
+    fn shortest_witnesses() {
+        let cases = [
+            ("ab{2,}c", Some("abbc")),
+            ("[x-z]", Some("x")),
+            ("\\d+$", Some("0")),
+            ("é", Some("é")),
+            ("[^\\s\\S]", None),
+            ("", Some("")),
+            ("a*$", Some("")),
+        ];
+        for (pattern, expected) in cases {
+            let fsm = Fsm::compile(pattern).unwrap();
+            assert_eq!(
+                fsm.shortest_accepted_string().as_deref(),
+                expected,
+                "{}",
+                pattern
+            );
+            assert_eq!(fsm.is_empty(), expected.is_none());
+        }
+    }
+
+    #[test]
+    fn complement_edges() {
+        let everything = Fsm::compile("").unwrap();
+        assert!(everything.complement().is_empty());
+        assert!(everything.complement().complement().match_str("xyz"));
+        let nothing = Fsm::compile("[^\\s\\S]").unwrap();
+        assert_eq!(
+            nothing.complement().shortest_accepted_string().as_deref(),
+            Some("")
+        );
+
+        let a = Fsm::compile("a$").unwrap();
+        let not_a = a.complement();
+        assert_eq!(not_a.shortest_accepted_string().as_deref(), Some(""));
+        assert!(a.intersection(&not_a).is_empty());
+        assert!(a.union(&not_a).complement().is_empty());
+    }
+}
@@ -0,0 +1,420 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::class::CharClass;
+use crate::CompileError;
+
+/// Largest bound accepted in `{m}`, `{m,}` and `{m,n}`.
+pub const MAX_REPEAT: u32 = 1000;
+
+/// Options changing how a pattern is read, set through `RegexBuilder` or
+/// inline with `(?ims)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    /// `i`: letters match both their upper and lower case.
+    pub case_insensitive: bool,
+    /// `m`: `^` and `$` match at the start and end of every line.
+    pub multi_line: bool,
+    /// `s`: `.` matches `\n` too.
+    pub dot_matches_new_line: bool,
+}
+
+/// Zero-width assertions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Look {
+    StartText,
+    EndText,
+    /// At the start of input or after a `\n`.
+    StartLine,
+    /// At the end of input or before a `\n`.
+    EndLine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ast {
+    Empty,
+    Class(CharClass),
+    Look(Look),
+    Concat(Vec<Ast>),
+    /// Matches one of the alternatives, preferring the first ones. Patterns
+    /// take `|` literally, so only globs build it.
+    Alternate(Vec<Ast>),
+    /// `greedy` repetitions prefer to match one more time, lazy ones to
+    /// stop.
+    Repeat {
+        ast: Box<Ast>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+    /// A non-capturing group.
+    Group(Box<Ast>),
+    /// A capturing group, numbered from 1 in the order it opens.
+    Capture {
+        index: usize,
+        name: Option<String>,
+        ast: Box<Ast>,
+    },
+    /// `\1` to `\9`: the text captured by a group. Only the backtracking
+    /// engine supports it.
+    BackRef {
+        index: usize,
+        case_insensitive: bool,
+        /// Byte offset of the `\` in the pattern.
+        pos: usize,
+    },
+    /// `(?=...)`, `(?!...)`, `(?<=...)` and `(?<!...)`. Only the
+    /// backtracking engine supports them.
+    LookAround {
+        ahead: bool,
+        negate: bool,
+        ast: Box<Ast>,
+        /// Byte offset of the `(` in the pattern.
+        pos: usize,
+    },
+}
+
+pub fn parse(src: &str, flags: Flags) -> Result<Ast, CompileError> {
+    let mut chars = src.char_indices().peekable();
+    let mut names = Vec::new();
+    let ast = parse_concat(&mut chars, flags, &mut names)?;
+    match chars.next() {
+        Some((pos, _)) => Err(CompileError::new(pos, "unmatched closing parenthesis")),
+        None => Ok(ast),
+    }
+}
+
+/// Parses atoms with their quantifiers up to the end of the pattern or of
+/// the enclosing group. Inline flags like `(?i)` last until then too.
+/// `names` holds the name of every capturing group opened so far.
+fn parse_concat(
+    chars: &mut PatternChars,
+    mut flags: Flags,
+    names: &mut Vec<Option<String>>,
+) -> Result<Ast, CompileError> {
+    let mut items = Vec::new();
+    while let Some(&(pos, c)) = chars.peek() {
+        if c == ')' {
+            break;
+        }
+        if matches!(c, '*' | '+' | '?') || (c == '{' && parse_bounds(chars)?.is_some()) {
+            return Err(CompileError::new(
+                pos,
+                "quantifier without a preceding atom",
+            ));
+        }
+        chars.next();
+        let ast = match c {
+            '(' => {
+                if let Some((ahead, negate)) = parse_look_around(chars) {
+                    let ast = Box::new(parse_group(chars, pos, flags, names)?);
+                    Ast::LookAround {
+                        ahead,
+                        negate,
+                        ast,
+                        pos,
+                    }
+                } else if chars.next_if(|&(_, c)| c == '?').is_none() {
+                    let index = names.len() + 1;
+                    names.push(None);
+                    let ast = Box::new(parse_group(chars, pos, flags, names)?);
+                    Ast::Capture {
+                        index,
+                        name: None,
+                        ast,
+                    }
+                } else if chars.next_if(|&(_, c)| c == 'P').is_some()
+                    || chars.peek().is_some_and(|&(_, c)| c == '<')
+                {
+                    let name = parse_group_name(chars, pos, names)?;
+                    let index = names.len() + 1;
+                    names.push(Some(name.clone()));
+                    let ast = Box::new(parse_group(chars, pos, flags, names)?);
+                    Ast::Capture {
+                        index,
+                        name: Some(name),
+                        ast,
+                    }
+                } else {
+                    let mut inner = flags;
+                    if parse_flags(chars, pos, &mut inner)? == ')' {
+                        flags = inner;
+                        continue;
+                    }
+                    Ast::Group(Box::new(parse_group(chars, pos, inner, names)?))
+                }
+            }
+            '^' if flags.multi_line => Ast::Look(Look::StartLine),
+            '^' => Ast::Look(Look::StartText),
+            '$' if flags.multi_line => Ast::Look(Look::EndLine),
+            '$' => Ast::Look(Look::EndText),
+            '.' if flags.dot_matches_new_line => Ast::Class(CharClass::any()),
+            '.' => Ast::Class(CharClass::single('\n').negate()),
+            '[' => Ast::Class(parse_bracket(chars, pos, flags)?),
+            '\\' => match chars.next_if(|&(_, c)| matches!(c, '1'..='9')) {
+                Some((_, digit)) => {
+                    let index = digit as usize - '0' as usize;
+                    if index > names.len() {
+                        return Err(CompileError::new(pos, "invalid backreference"));
+                    }
+                    Ast::BackRef {
+                        index,
+                        case_insensitive: flags.case_insensitive,
+                        pos,
+                    }
+                }
+                None => Ast::Class(fold(parse_escape(chars, pos)?, flags)),
+            },
+            c => Ast::Class(fold(CharClass::single(c), flags)),
+        };
+        items.push(parse_quantifiers(chars, ast)?);
+    }
+    Ok(match items.len() {
+        0 => Ast::Empty,
+        1 => items.pop().unwrap(),
+        _ => Ast::Concat(items),
+    })
+}
+
+/// Parses the rest of a group opened at `pos` up to its `)`.
+fn parse_group(
+    chars: &mut PatternChars,
+    pos: usize,
+    flags: Flags,
+    names: &mut Vec<Option<String>>,
+) -> Result<Ast, CompileError> {
+    let ast = parse_concat(chars, flags, names)?;
+    match chars.next() {
+        Some(_) => Ok(ast),
+        None => Err(CompileError::new(pos, "unclosed group")),
+    }
+}
+
+/// Parses the `?=`, `?!`, `?<=` or `?<!` opening a look-around, returning
+/// whether it looks ahead and whether it is negated. Anything else leaves
+/// `chars` untouched.
+fn parse_look_around(chars: &mut PatternChars) -> Option<(bool, bool)> {
+    let mut lookahead = chars.clone();
+    lookahead.next_if(|&(_, c)| c == '?')?;
+    let ahead = lookahead.next_if(|&(_, c)| c == '<').is_none();
+    let (_, c) = lookahead.next_if(|&(_, c)| c == '=' || c == '!')?;
+    *chars = lookahead;
+    Some((ahead, c == '!'))
+}
+
+/// Parses the flags of `(?flags)` or `(?flags:...)` after the `?`, applying
+/// them to `flags`. Returns the character ending them, `)` or `:`.
+fn parse_flags(
+    chars: &mut PatternChars,
+    pos: usize,
+    flags: &mut Flags,
+) -> Result<char, CompileError> {
+    let mut enable = true;
+    loop {
+        let (p, c) = chars
+            .next()
+            .ok_or(CompileError::new(pos, "unclosed group"))?;
+        match c {
+            'i' => flags.case_insensitive = enable,
+            'm' => flags.multi_line = enable,
+            's' => flags.dot_matches_new_line = enable,
+            '-' if enable => enable = false,
+            ')' | ':' => return Ok(c),
+            _ => return Err(CompileError::new(p, "unknown flag")),
+        }
+    }
+}
+
+/// Parses the `<name>` of `(?<name>...)` or `(?P<name>...)`. A name is made
+/// of letters, digits and `_`, does not start with a digit and is not used by
+/// another group.
+fn parse_group_name(
+    chars: &mut PatternChars,
+    pos: usize,
+    names: &[Option<String>],
+) -> Result<String, CompileError> {
+    if chars.next_if(|&(_, c)| c == '<').is_none() {
+        return Err(CompileError::new(pos, "invalid group name"));
+    }
+    let mut name = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '>')) => break,
+            Some((_, c)) if c.is_alphanumeric() || c == '_' => name.push(c),
+            _ => return Err(CompileError::new(pos, "invalid group name")),
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(CompileError::new(pos, "invalid group name"));
+    }
+    if names.iter().flatten().any(|other| *other == name) {
+        return Err(CompileError::new(pos, "duplicate group name"));
+    }
+    Ok(name)
+}
+
+fn fold(class: CharClass, flags: Flags) -> CharClass {
+    if flags.case_insensitive {
+        class.case_fold()
+    } else {
+        class
+    }
+}
+
+fn parse_quantifiers(chars: &mut PatternChars, mut ast: Ast) -> Result<Ast, CompileError> {
+    while let Some(&(_, c)) = chars.peek() {
+        let (min, max) = match c {
+            '*' => (0, None),
+            '+' => (1, None),
+            '?' => (0, Some(1)),
+            '{' => match parse_bounds(chars)? {
+                Some(bounds) => bounds,
+                None => break,
+            },
+            _ => break,
+        };
+        if c != '{' {
+            chars.next();
+        }
+        let greedy = chars.next_if(|&(_, c)| c == '?').is_none();
+        ast = Ast::Repeat {
+            ast: Box::new(ast),
+            min,
+            max,
+            greedy,
+        };
+    }
+    Ok(ast)
+}
+
+/// Parses `{m}`, `{m,}` or `{m,n}` when `chars` is at such a `{`. Anything
+/// else leaves `chars` untouched, and the `{` is taken literally.
+fn parse_bounds(chars: &mut PatternChars) -> Result<Option<(u32, Option<u32>)>, CompileError> {
+    let mut lookahead = chars.clone();
+    let (pos, _) = lookahead.next().unwrap();
+    let number = |lookahead: &mut PatternChars| {
+        let mut digits = String::new();
+        while let Some((_, c)) = lookahead.next_if(|(_, c)| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        digits
+    };
+
+    let min = number(&mut lookahead);
+    let max = if lookahead.next_if(|&(_, c)| c == ',').is_some() {
+        Some(number(&mut lookahead))
+    } else {
+        None
+    };
+    if min.is_empty() || lookahead.next_if(|&(_, c)| c == '}').is_none() {
+        return Ok(None);
+    }
+    *chars = lookahead;
+
+    let bound = |digits: &str| match digits.parse::<u32>() {
+        Ok(n) if n <= MAX_REPEAT => Ok(n),
+        _ => Err(CompileError::new(pos, "repetition bound exceeds 1000")),
+    };
+    let min = bound(&min)?;
+    let max = match max {
+        None => Some(min),
+        Some(max) if max.is_empty() => None,
+        Some(max) => Some(bound(&max)?),
+    };
+    if max.is_some_and(|max| max < min) {
+        return Err(CompileError::new(pos, "invalid repetition range"));
+    }
+    Ok(Some((min, max)))
+}
+
+type PatternChars<'a> = Peekable<CharIndices<'a>>;
+
+/// Parses the character after a `\`, which is either an escaped literal or a
+/// shorthand class such as `\d` or `\p{Alphabetic}`.
+fn parse_escape(chars: &mut PatternChars, pos: usize) -> Result<CharClass, CompileError> {
+    let (_, c) = chars
+        .next()
+        .ok_or(CompileError::new(pos, "trailing backslash"))?;
+    if let Some(class) = CharClass::perl(c) {
+        return Ok(class);
+    }
+    match c {
+        'p' | 'P' => {
+            if chars.next().map(|(_, c)| c) != Some('{') {
+                return Err(CompileError::new(pos, "expected `{` after \\p"));
+            }
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => return Err(CompileError::new(pos, "unterminated property name")),
+                }
+            }
+            let class = CharClass::property(&name)
+                .ok_or(CompileError::new(pos, "unknown Unicode property"))?;
+            Ok(if c == 'P' { class.negate() } else { class })
+        }
+        'n' => Ok(CharClass::single('\n')),
+        't' => Ok(CharClass::single('\t')),
+        'r' => Ok(CharClass::single('\r')),
+        c if c.is_alphanumeric() => Err(CompileError::new(pos, "unknown escape sequence")),
+        c => Ok(CharClass::single(c)),
+    }
+}
+
+/// Parses a bracket expression such as `[a-zé]`, `[^0-9]` or `[\p{Alphabetic}_]`
+/// after its opening `[`. Case folding applies before negation, so `(?i)[^a]`
+/// matches neither `a` nor `A`.
+fn parse_bracket(
+    chars: &mut PatternChars,
+    pos: usize,
+    flags: Flags,
+) -> Result<CharClass, CompileError> {
+    let mut class = CharClass::new();
+    let negated = chars.next_if(|&(_, c)| c == '^').is_some();
+    let mut first = true;
+    loop {
+        let (p, c) = chars
+            .next()
+            .ok_or(CompileError::new(pos, "unterminated bracket expression"))?;
+        let lo = match c {
+            ']' if !first => break,
+            '\\' => {
+                let escaped = parse_escape(chars, p)?;
+                match escaped.as_single() {
+                    Some(c) => c,
+                    None => {
+                        class.union(&escaped);
+                        first = false;
+                        continue;
+                    }
+                }
+            }
+            c => c,
+        };
+        first = false;
+        if chars.next_if(|&(_, c)| c == '-').is_none() {
+            class.push(lo..=lo);
+            continue;
+        }
+        let hi = match chars.next() {
+            Some((_, ']')) => {
+                class.push(lo..=lo);
+                class.push('-'..='-');
+                break;
+            }
+            Some((p, '\\')) => parse_escape(chars, p)?
+                .as_single()
+                .ok_or(CompileError::new(p, "invalid range end"))?,
+            Some((_, hi)) => hi,
+            None => return Err(CompileError::new(pos, "unterminated bracket expression")),
+        };
+        if hi < lo {
+            return Err(CompileError::new(pos, "invalid range"));
+        }
+        class.push(lo..=hi);
+    }
+    let class = fold(class, flags);
+    Ok(if negated { class.negate() } else { class })
+}
@@ -0,0 +1,134 @@
+use crate::nfa::{Context, Inst, Nfa};
+
+/// Capture slots: byte offsets where each group starts and ends.
+pub type Slots = Vec<Option<usize>>;
+
+/// Threads waiting at the same position, in priority order, each with its
+/// own capture slots. `visited` lists every pc marked in `seen`.
+struct Threads {
+    pcs: Vec<usize>,
+    seen: Vec<bool>,
+    visited: Vec<usize>,
+    slots: Vec<Slots>,
+}
+
+impl Threads {
+    fn new(nfa: &Nfa) -> Self {
+        Self {
+            pcs: Vec::new(),
+            seen: vec![false; nfa.insts.len()],
+            visited: Vec::new(),
+            slots: vec![Vec::new(); nfa.insts.len()],
+        }
+    }
+
+    fn clear(&mut self) {
+        for &pc in self.visited.iter() {
+            self.seen[pc] = false;
+        }
+        self.visited.clear();
+        self.pcs.clear();
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+impl Nfa {
+    /// Finds the leftmost match in `text` starting at or after byte offset
+    /// `start`, simulating all threads at once like Pike's VM. Among matches
+    /// starting at the same position the one preferred by the `Split`s wins,
+    /// as in Perl. Returns the capture slots of the match.
+    pub fn search(&self, text: &str, start: usize) -> Option<Slots> {
+        let mut current = Threads::new(self);
+        let mut next = Threads::new(self);
+        let mut stack = Vec::new();
+        let mut slots = vec![None; self.slots()];
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            if matched.is_none() {
+                slots.fill(None);
+                self.add(&mut current, &mut stack, &mut slots, self.start, text, pos);
+            }
+            if current.pcs.is_empty() && matched.is_some() {
+                break;
+            }
+            let c = text[pos..].chars().next();
+            let after = pos + c.map_or(0, char::len_utf8);
+            for i in 0..current.pcs.len() {
+                let pc = current.pcs[i];
+                match &self.insts[pc] {
+                    Inst::Match => {
+                        // Threads after this one have a lower priority.
+                        matched = Some(current.slots[pc].clone());
+                        break;
+                    }
+                    Inst::Class(class, to) if c.is_some_and(|c| class.contains(c)) => {
+                        slots.clone_from(&current.slots[pc]);
+                        self.add(&mut next, &mut stack, &mut slots, *to, text, after);
+                    }
+                    _ => {}
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            if c.is_none() {
+                break;
+            }
+            pos = after;
+        }
+        matched
+    }
+
+    /// Adds the thread at `pc` to `threads`, following the instructions
+    /// which do not consume input, with `slots` saved at `pos`.
+    fn add(
+        &self,
+        threads: &mut Threads,
+        stack: &mut Vec<Frame>,
+        slots: &mut Slots,
+        pc: usize,
+        text: &str,
+        pos: usize,
+    ) {
+        let ctx = Context::at(text, pos);
+        stack.push(Frame::Explore(pc));
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+            threads.visited.push(pc);
+            match self.insts[pc] {
+                Inst::Split(a, b) => {
+                    stack.push(Frame::Explore(b));
+                    stack.push(Frame::Explore(a));
+                }
+                Inst::Look(look, next) => {
+                    if ctx.holds(look) {
+                        stack.push(Frame::Explore(next));
+                    }
+                }
+                Inst::Save(slot, next) => {
+                    stack.push(Frame::Restore(slot, slots[slot]));
+                    slots[slot] = Some(pos);
+                    stack.push(Frame::Explore(next));
+                }
+                Inst::Class(..) | Inst::Match => {
+                    threads.pcs.push(pc);
+                    threads.slots[pc].clone_from(slots);
+                }
+            }
+        }
+    }
+}
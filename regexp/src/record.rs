@@ -0,0 +1,319 @@
+//! Recording and replaying the transitions of a machine. A log has one
+//! transition per line, written `timestamp from event to`, the timestamp
+//! being milliseconds since the Unix epoch:
+//!
+//! ```text
+//! 1697712000123 Locked Coin Unlocked
+//! 1697712004567 Unlocked Push Locked
+//! ```
+//!
+//! States and events are written with `Display`, and read back by looking
+//! for the state or event which displays that way.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One line of a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<S, E> {
+    pub timestamp: u64,
+    pub from: S,
+    pub event: E,
+    pub to: S,
+}
+
+impl<S: fmt::Display, E: fmt::Display> fmt::Display for Record<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.timestamp, self.from, self.event, self.to
+        )
+    }
+}
+
+impl<S, E> Record<S, E> {
+    /// The transition from `from` to `to` on `event`, taken now.
+    pub fn now(from: S, event: E, to: S) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        Self {
+            timestamp,
+            from,
+            event,
+            to,
+        }
+    }
+}
+
+impl<S: Copy + fmt::Display, E: Copy + fmt::Display> Record<S, E> {
+    /// Reads a line, finding its states in `states` and its event in
+    /// `events`.
+    pub fn parse(line: &str, states: &[S], events: &[E]) -> Result<Self, ParseError> {
+        let mut fields = line.split_whitespace();
+        let mut field = |msg| fields.next().ok_or(ParseError::new(msg));
+        let timestamp = field("missing timestamp")?;
+        let from = field("missing state")?;
+        let event = field("missing event")?;
+        let to = field("missing state")?;
+        if fields.next().is_some() {
+            return Err(ParseError::new("trailing fields"));
+        }
+        Ok(Self {
+            timestamp: timestamp
+                .parse()
+                .map_err(|_| ParseError::new("invalid timestamp"))?,
+            from: find(states, from).ok_or(ParseError::new("unknown state"))?,
+            event: find(events, event).ok_or(ParseError::new("unknown event"))?,
+            to: find(states, to).ok_or(ParseError::new("unknown state"))?,
+        })
+    }
+}
+
+fn find<T: Copy + fmt::Display>(values: &[T], name: &str) -> Option<T> {
+    values
+        .iter()
+        .copied()
+        .find(|value| value.to_string() == name)
+}
+
+/// A line of a log which cannot be read. Lines are counted from 1.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: &'static str,
+}
+
+impl ParseError {
+    fn new(msg: &'static str) -> Self {
+        Self { line: 0, msg }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on line {}", self.msg, self.line)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Runs a `next_state` function and logs every transition it makes. A
+/// machine which decides its transitions otherwise, such as an
+/// `extended::Machine`, is logged by writing a `Record::now` for every
+/// transition it takes.
+pub struct Recorder<S, E, W> {
+    state: S,
+    next_state: fn(S, E) -> S,
+    log: W,
+}
+
+impl<S, E, W> Recorder<S, E, W>
+where
+    S: Copy + fmt::Display,
+    E: Copy + fmt::Display,
+    W: Write,
+{
+    pub fn new(initial: S, next_state: fn(S, E) -> S, log: W) -> Self {
+        Self {
+            state: initial,
+            next_state,
+            log,
+        }
+    }
+
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    /// Moves to the next state and writes the transition to the log.
+    pub fn handle(&mut self, event: E) -> io::Result<S> {
+        let to = (self.next_state)(self.state, event);
+        writeln!(self.log, "{}", Record::now(self.state, event, to))?;
+        self.log.flush()?;
+        self.state = to;
+        Ok(to)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.log
+    }
+}
+
+/// The first transition of a log the machine does not make again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence<S, E> {
+    /// Line of the transition, counted from 1.
+    pub line: usize,
+    /// The transition as logged.
+    pub recorded: Record<S, E>,
+    /// The state the replay was in before the event.
+    pub from: S,
+    /// The state the replay reached on the event.
+    pub to: S,
+}
+
+impl<S: fmt::Display, E: fmt::Display> fmt::Display for Divergence<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: recorded {} --{}--> {}, replayed {} --{}--> {}",
+            self.line,
+            self.recorded.from,
+            self.recorded.event,
+            self.recorded.to,
+            self.from,
+            self.recorded.event,
+            self.to
+        )
+    }
+}
+
+/// Runs the events of `log` through `next_state`, starting in the first
+/// logged state, and returns the first transition which does not go from
+/// and to the logged states. Empty lines are skipped. A line which cannot be
+/// read is an `InvalidData` error wrapping a `ParseError`.
+///
+/// `next_state` is called once per event, in the order of the log, so it may
+/// drive a machine with a context and return the state it reached.
+pub fn replay<S, E>(
+    log: impl BufRead,
+    mut next_state: impl FnMut(S, E) -> S,
+    states: &[S],
+    events: &[E],
+) -> io::Result<Option<Divergence<S, E>>>
+where
+    S: Copy + PartialEq + fmt::Display,
+    E: Copy + fmt::Display,
+{
+    let mut state = None;
+    for (i, line) in log.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded = Record::parse(&line, states, events).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                ParseError { line: i + 1, ..err },
+            )
+        })?;
+        let from = state.unwrap_or(recorded.from);
+        let to = next_state(from, recorded.event);
+        if from != recorded.from || to != recorded.to {
+            return Ok(Some(Divergence {
+                line: i + 1,
+                recorded,
+                from,
+                to,
+            }));
+        }
+        state = Some(to);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::StateMachine;
+    use crate::turnstile::{coin_counter, next_state, Counter, Event, State, Turnstile};
+
+    fn replay_str(log: &str) -> io::Result<Option<Divergence<State, Event>>> {
+        replay(
+            log.as_bytes(),
+            next_state,
+            Turnstile::STATES,
+            Turnstile::EVENTS,
+        )
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut recorder = Recorder::new(State::Locked, next_state, Vec::new());
+        for event in [Event::Coin, Event::Push, Event::Push] {
+            recorder.handle(event).unwrap();
+        }
+        assert_eq!(recorder.state(), State::Locked);
+        let log = String::from_utf8(recorder.into_inner()).unwrap();
+        let transitions = log
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transitions,
+            [
+                "Locked Coin Unlocked",
+                "Unlocked Push Locked",
+                "Locked Push Locked"
+            ]
+        );
+        assert_eq!(replay_str(&log).unwrap(), None);
+    }
+
+    #[test]
+    fn first_divergence() {
+        let log = "1 Locked Coin Unlocked\n\n2 Unlocked Coin Locked\n3 Locked Push Locked\n";
+        let divergence = replay_str(log).unwrap().unwrap();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.to, State::Unlocked);
+        assert_eq!(
+            divergence.to_string(),
+            "line 3: recorded Unlocked --Coin--> Locked, replayed Unlocked --Coin--> Unlocked"
+        );
+
+        // A log which skips a transition diverges on the state it starts from.
+        let divergence = replay_str("1 Locked Coin Unlocked\n2 Locked Coin Unlocked\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!((divergence.line, divergence.from), (2, State::Unlocked));
+
+        let err = replay_str("1 Locked Coin Unlocked\n2 Locked Kick Locked\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unknown event on line 2");
+    }
+
+    #[test]
+    fn extended_machine() {
+        let full = || {
+            coin_counter(Counter {
+                capacity: Some(1),
+                ..Counter::default()
+            })
+        };
+        let mut turnstile = full();
+        let mut log = Vec::new();
+        for event in [Event::Coin, Event::Push, Event::Coin] {
+            let from = turnstile.state();
+            turnstile.handle(event).unwrap();
+            let record = Record::now(from, event, turnstile.state());
+            writeln!(log, "{}", record).unwrap();
+        }
+        // The box is full, so the second coin leaves the turnstile locked.
+        assert_eq!(turnstile.state(), State::Locked);
+
+        let mut turnstile = full();
+        let replayed = replay(
+            log.as_slice(),
+            |_, event| {
+                let _ = turnstile.handle(event);
+                turnstile.state()
+            },
+            Turnstile::STATES,
+            Turnstile::EVENTS,
+        );
+        assert_eq!(replayed.unwrap(), None);
+        let divergence = replay(
+            log.as_slice(),
+            next_state,
+            Turnstile::STATES,
+            Turnstile::EVENTS,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!((divergence.line, divergence.to), (3, State::Unlocked));
+    }
+}
@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+
+use crate::captures::{Captures, Matches};
+use crate::Fsm;
+
+/// What a match is replaced with: a `&str` or `String` expanded with
+/// `Captures::expand`, or a closure given the groups of the match.
+pub trait Replacer {
+    fn append(&mut self, caps: &Captures<'_>, dst: &mut String);
+}
+
+impl Replacer for &str {
+    fn append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for &String {
+    fn append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for String {
+    fn append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+impl Fsm {
+    /// Replaces the leftmost match in `text`. `text` is borrowed back when
+    /// there is no match.
+    pub fn replace<'t>(&self, text: &'t str, rep: impl Replacer) -> Cow<'t, str> {
+        self.replacen(text, 1, rep)
+    }
+
+    /// Replaces every match in `text`, the way `find_iter` finds them.
+    pub fn replace_all<'t>(&self, text: &'t str, rep: impl Replacer) -> Cow<'t, str> {
+        self.replacen(text, 0, rep)
+    }
+
+    /// Replaces the first `limit` matches in `text`, or all of them if
+    /// `limit` is `0`.
+    pub fn replacen<'t>(
+        &self,
+        text: &'t str,
+        limit: usize,
+        mut rep: impl Replacer,
+    ) -> Cow<'t, str> {
+        let mut matches = self.find_iter(text);
+        let mut dst = String::new();
+        let mut last = 0;
+        let mut count = 0;
+        while let Some(caps) = matches.next_captures() {
+            let range = caps.range(0).unwrap();
+            dst.push_str(&text[last..range.start]);
+            rep.append(&caps, &mut dst);
+            last = range.end;
+            count += 1;
+            if count == limit {
+                break;
+            }
+        }
+        if count == 0 {
+            return Cow::Borrowed(text);
+        }
+        dst.push_str(&text[last..]);
+        Cow::Owned(dst)
+    }
+
+    /// The parts of `text` between the matches.
+    ///
+    /// ```
+    /// use regexp::Fsm;
+    ///
+    /// let fsm = Fsm::compile("\\s*,\\s*").unwrap();
+    /// let parts = fsm.split("a, b ,c").collect::<Vec<_>>();
+    /// assert_eq!(parts, vec!["a", "b", "c"]);
+    /// ```
+    pub fn split<'a>(&'a self, text: &'a str) -> Split<'a> {
+        Split {
+            matches: self.find_iter(text),
+            text,
+            last: 0,
+            done: false,
+        }
+    }
+
+    /// Like `split`, giving at most `limit` parts. The last one is the rest
+    /// of `text`.
+    pub fn splitn<'a>(&'a self, text: &'a str, limit: usize) -> SplitN<'a> {
+        SplitN {
+            split: self.split(text),
+            limit,
+        }
+    }
+}
+
+/// Iterator returned by `Fsm::split`.
+pub struct Split<'a> {
+    matches: Matches<'a>,
+    text: &'a str,
+    last: usize,
+    done: bool,
+}
+
+impl<'a> Split<'a> {
+    /// The text after the last part given, ending the iteration.
+    fn rest(&mut self) -> Option<&'a str> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some(&self.text[self.last..])
+    }
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.done {
+            return None;
+        }
+        match self.matches.next() {
+            Some(range) => {
+                let part = &self.text[self.last..range.start];
+                self.last = range.end;
+                Some(part)
+            }
+            None => self.rest(),
+        }
+    }
+}
+
+/// Iterator returned by `Fsm::splitn`.
+pub struct SplitN<'a> {
+    split: Split<'a>,
+    limit: usize,
+}
+
+impl<'a> Iterator for SplitN<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                self.split.rest()
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_borrows_without_match() {
+        let fsm = Fsm::compile("\\d+").unwrap();
+        assert!(matches!(
+            fsm.replace_all("no digits", "#"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(fsm.replace("no digits", "#"), Cow::Borrowed(_)));
+        assert_eq!(fsm.replace("a1b22c333", "#"), "a#b22c333");
+        assert_eq!(fsm.replace_all("a1b22c333", "#"), "a#b#c#");
+        assert_eq!(fsm.replacen("a1b22c333", 2, "#"), "a#b#c333");
+    }
+
+    #[test]
+    fn replace_expands_groups() {
+        let fsm = Fsm::compile("(?<key>\\w+)=(\\w+)").unwrap();
+        assert_eq!(fsm.replace_all("a=1, b=2", "$2=${key}"), "1=a, 2=b");
+        assert_eq!(fsm.replace_all("a=1", "$$key"), "$key");
+        assert_eq!(fsm.replace_all("a=1", String::from("[$0]")), "[a=1]");
+    }
+
+    #[test]
+    fn replace_with_closure() {
+        let fsm = Fsm::compile("\\d+").unwrap();
+        let doubled = fsm.replace_all("3 apples and 12 pears", |caps: &Captures| {
+            (caps.get(0).unwrap().parse::<u32>().unwrap() * 2).to_string()
+        });
+        assert_eq!(doubled, "6 apples and 24 pears");
+
+        let mut count = 0;
+        let numbered = fsm.replace_all("x1 x2", |_: &Captures| {
+            count += 1;
+            format!("<{}>", count)
+        });
+        assert_eq!(numbered, "x<1> x<2>");
+    }
+
+    #[test]
+    fn replace_empty_matches() {
+        let fsm = Fsm::compile("x*").unwrap();
+        assert_eq!(fsm.replace_all("abxc", "-"), "-a-b-c-");
+        assert_eq!(fsm.replace_all("é", "-"), "-é-");
+    }
+
+    #[test]
+    fn split_and_splitn() {
+        let fsm = Fsm::compile(",").unwrap();
+        let parts = |text| fsm.split(text).collect::<Vec<_>>();
+        assert_eq!(parts("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(parts(",a,"), vec!["", "a", ""]);
+        assert_eq!(parts(""), vec![""]);
+        assert_eq!(parts("abc"), vec!["abc"]);
+
+        let parts = |text, n| fsm.splitn(text, n).collect::<Vec<_>>();
+        assert_eq!(parts("a,b,c", 2), vec!["a", "b,c"]);
+        assert_eq!(parts("a,b,c", 5), vec!["a", "b", "c"]);
+        assert_eq!(parts("a,b,c", 1), vec!["a,b,c"]);
+        assert!(parts("a,b,c", 0).is_empty());
+
+        let fsm = Fsm::compile("").unwrap();
+        assert_eq!(fsm.split("ab").collect::<Vec<_>>(), vec!["", "a", "b", ""]);
+    }
+}
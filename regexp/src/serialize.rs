@@ -0,0 +1,269 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{Fsm, FsmColumn, FsmElement};
+
+const MAGIC: &[u8; 4] = b"RXFS";
+/// Version of the format written by `Fsm::to_bytes`. `Fsm::from_bytes`
+/// rejects any other.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Why `Fsm::from_bytes` rejected its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Byte offset in the input where the error was detected.
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.msg, self.pos)
+    }
+}
+
+impl Error for DecodeError {}
+
+impl Fsm {
+    /// Serializes the compressed table. All numbers are little-endian:
+    ///
+    /// - header: `RXFS`, the format version as `u16` and a reserved `u16`;
+    /// - state count `n` (the failed state included), segment count and
+    ///   class count as `u32`;
+    /// - alphabet: the first character and class of every segment, as two
+    ///   `u32`;
+    /// - transitions: `n × classes` states as `u32`, `n` being the accepting
+    ///   state;
+    /// - accepting set: the number of states accepting at the end of input
+    ///   followed by these states in increasing order, as `u32`.
+    ///
+    /// The NFA is not stored, so an `Fsm` read back finds matches the way
+    /// automata built out of columns do.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let table = &self.table;
+        let n = table.accept;
+        let k = table.classes.count();
+        let segments = table.classes.segments();
+        let eoi = (0..n).filter(|&s| table.eoi[s]).collect::<Vec<_>>();
+
+        let mut bytes = Vec::with_capacity(20 + segments.len() * 8 + n * k * 4 + eoi.len() * 4);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        let mut put = |value: usize| bytes.extend_from_slice(&(value as u32).to_le_bytes());
+        put(n);
+        put(segments.len());
+        put(k);
+        for &(start, class) in segments.iter() {
+            put(start as usize);
+            put(class as usize);
+        }
+        for i in 0..n * k {
+            put(table.ts.get(i));
+        }
+        put(eoi.len());
+        for state in eoi {
+            put(state);
+        }
+        bytes
+    }
+
+    /// Reads an automaton written by `to_bytes`. Everything is checked, so
+    /// that matching never goes out of bounds, whatever the input.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Fsm, DecodeError> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err(r.error(0, "not a serialized Fsm"));
+        }
+        let version = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(r.error(4, "unsupported format version"));
+        }
+        r.take(2)?;
+
+        let n = r.u32()?;
+        let segment_count = r.u32()?;
+        let k = r.u32()?;
+        if n == 0 || segment_count == 0 || k == 0 {
+            return Err(r.error(r.pos - 12, "empty automaton"));
+        }
+        // Make sure the sizes fit in the input before allocating anything.
+        let cells = n
+            .checked_mul(k)
+            .filter(|&cells| cells.saturating_add(segment_count * 2) <= r.remaining() / 4)
+            .ok_or(r.error(r.pos, "unexpected end of input"))?;
+
+        let mut segments = Vec::with_capacity(segment_count);
+        for i in 0..segment_count {
+            let pos = r.pos;
+            let start = char::from_u32(r.u32()? as u32)
+                .filter(|&c| (i == 0) == (c == '\0'))
+                .filter(|&c| segments.last().is_none_or(|&(prev, _)| prev < c))
+                .ok_or(r.error(pos, "invalid segment start"))?;
+            let class = r.u32()?;
+            if class >= k {
+                return Err(r.error(pos + 4, "class out of range"));
+            }
+            segments.push((start, class));
+        }
+
+        let mut ts = Vec::with_capacity(cells);
+        for i in 0..cells {
+            let pos = r.pos;
+            let next = r.u32()?;
+            if next > n || (i < k && next != 0) {
+                return Err(r.error(pos, "transition out of range"));
+            }
+            ts.push(next);
+        }
+
+        let mut eoi = vec![false; n];
+        let count = r.u32()?;
+        let mut prev = 0;
+        for _ in 0..count {
+            let pos = r.pos;
+            let state = r.u32()?;
+            if state == 0 || state >= n || state <= prev {
+                return Err(r.error(pos, "accepting state out of range"));
+            }
+            eoi[state] = true;
+            prev = state;
+        }
+        if r.remaining() > 0 {
+            return Err(r.error(r.pos, "trailing bytes"));
+        }
+
+        let cs = (0..n)
+            .map(|state| {
+                let mut col = FsmColumn::new();
+                col.ts = segments
+                    .iter()
+                    .map(|&(start, class)| (start, FsmElement::new(ts[state * k + class], 1)))
+                    .collect();
+                if eoi[state] {
+                    col.end = FsmElement::new(n, 1);
+                }
+                col.merge();
+                col
+            })
+            .collect();
+        Ok(Fsm::from_columns(cs))
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, pos: usize, msg: &'static str) -> DecodeError {
+        DecodeError { pos, msg }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.remaining() < len {
+            return Err(self.error(self.bytes.len(), "unexpected end of input"));
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: [&str; 9] = ["", "a", "abc", "a1", "ééx", "x\ny", "aaaaab", "😀", "zz9"];
+
+    #[test]
+    fn round_trip() {
+        let patterns = [
+            "",
+            "abc",
+            "a*b?$",
+            "\\w+\\d$",
+            "é+x",
+            "(?m)x$\\n^y",
+            "[^\\s\\S]",
+        ];
+        for pattern in patterns {
+            let fsm = Fsm::compile(pattern).unwrap();
+            let bytes = fsm.to_bytes();
+            let read = Fsm::from_bytes(&bytes).unwrap();
+            assert_eq!(read.state_count(), fsm.state_count());
+            assert_eq!(read.to_bytes(), bytes, "{}", pattern);
+            for input in INPUTS {
+                assert_eq!(read.match_str(input), fsm.match_str(input), "{}", pattern);
+            }
+        }
+        let unminimized = Fsm::compile_unminimized("a*a*b").unwrap();
+        let read = Fsm::from_bytes(&unminimized.to_bytes()).unwrap();
+        assert!(read.match_str("aab") && !read.match_str("aa"));
+    }
+
+    #[test]
+    fn header() {
+        let bytes = Fsm::compile("a").unwrap().to_bytes();
+        assert_eq!(&bytes[..6], b"RXFS\x01\x00");
+
+        let mut other = bytes.clone();
+        other[4] = 2;
+        let e = Fsm::from_bytes(&other).err().unwrap();
+        assert_eq!((e.pos, e.msg), (4, "unsupported format version"));
+        assert_eq!(e.to_string(), "unsupported format version at byte 4");
+        assert_eq!(
+            Fsm::from_bytes(b"PNG\0").err().map(|e| e.msg),
+            Some("not a serialized Fsm")
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Fsm::from_bytes(&trailing).err().map(|e| e.msg),
+            Some("trailing bytes")
+        );
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let bytes = Fsm::compile("[a-c]+\\d$").unwrap().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(
+                Fsm::from_bytes(&bytes[..len]).is_err(),
+                "truncated to {}",
+                len
+            );
+        }
+
+        // The first transition of state 1 points past the accepting state.
+        let k = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        let segments = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let mut corrupted = bytes.clone();
+        let cell = 20 + segments * 8 + k * 4;
+        corrupted[cell..cell + 4].copy_from_slice(&1000u32.to_le_bytes());
+        let e = Fsm::from_bytes(&corrupted).err().unwrap();
+        assert_eq!((e.pos, e.msg), (cell, "transition out of range"));
+
+        // Whatever byte is damaged, the result is an error or an automaton
+        // which can be run safely.
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut damaged = bytes.clone();
+                damaged[i] ^= flip;
+                if let Ok(fsm) = Fsm::from_bytes(&damaged) {
+                    for input in INPUTS {
+                        fsm.match_str(input);
+                    }
+                }
+            }
+        }
+    }
+}
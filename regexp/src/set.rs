@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use crate::table::{CharClasses, Transitions};
+use crate::{CompileError, Fsm, MAX_STATES};
+
+/// Several patterns compiled into one automaton, the product of their
+/// `Fsm`s, so an input is matched against all of them in a single scan.
+///
+/// A state of the product records the state of every pattern still running
+/// and the ids of the patterns which have already matched.
+///
+/// ```
+/// use regexp::RegexSet;
+///
+/// let set = RegexSet::new(["[a-z]+:", "\\d+", "error"]).unwrap();
+/// assert_eq!(set.matches("error: disk full"), vec![0, 2]);
+/// assert!(set.matches("-").is_empty());
+/// ```
+pub struct RegexSet {
+    len: usize,
+    classes: CharClasses,
+    ts: Transitions,
+    /// Ids of the patterns matched once a state is reached.
+    matched: Vec<Vec<usize>>,
+    /// Ids of the patterns matched when the input ends in a state.
+    eoi: Vec<Vec<usize>>,
+    /// Whether no more input can change the result.
+    done: Vec<bool>,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<Self, CompileError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let fsms = patterns
+            .into_iter()
+            .map(|pattern| Fsm::compile(pattern.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_fsms(&fsms)
+    }
+
+    /// Builds the product of `fsms`, exploring only the reachable states.
+    /// Pattern ids are the indices into `fsms`.
+    pub fn from_fsms(fsms: &[Fsm]) -> Result<Self, CompileError> {
+        // Classes of the product: segments which fall into the same class of
+        // every automaton.
+        let mut starts = fsms
+            .iter()
+            .flat_map(|fsm| Fsm::segment_starts(&fsm.cs))
+            .collect::<Vec<_>>();
+        starts.push('\0');
+        starts.sort();
+        starts.dedup();
+        let mut ids: HashMap<Vec<usize>, u32> = HashMap::new();
+        let mut reps = Vec::new();
+        let segments = starts
+            .iter()
+            .map(|&start| {
+                let key = fsms
+                    .iter()
+                    .map(|fsm| fsm.table.classes.get(start))
+                    .collect::<Vec<_>>();
+                let id = *ids.entry(key).or_insert_with(|| {
+                    reps.push(start);
+                    reps.len() as u32 - 1
+                });
+                (start, id)
+            })
+            .collect::<Vec<_>>();
+
+        // Patterns which accepted or failed are parked in state `0`.
+        type Key = (Vec<usize>, Vec<usize>);
+        let settle = |mut states: Vec<usize>, mut matched: Vec<usize>| -> Key {
+            for (id, state) in states.iter_mut().enumerate() {
+                if *state == fsms[id].table.accept {
+                    *state = 0;
+                    matched.push(id);
+                }
+            }
+            matched.sort();
+            (states, matched)
+        };
+
+        let mut keys = vec![settle(vec![1; fsms.len()], Vec::new())];
+        let mut index: HashMap<Key, usize> = HashMap::new();
+        index.insert(keys[0].clone(), 0);
+        let mut cells = Vec::new();
+        let mut state = 0;
+        while state < keys.len() {
+            let (states, matched) = keys[state].clone();
+            for &c in reps.iter() {
+                let next = states
+                    .iter()
+                    .zip(fsms)
+                    .map(|(&s, fsm)| if s == 0 { 0 } else { fsm.table.next(s, c) })
+                    .collect();
+                let key = settle(next, matched.clone());
+                let next = match index.get(&key) {
+                    Some(&next) => next,
+                    None => {
+                        if keys.len() >= MAX_STATES {
+                            return Err(CompileError::new(0, "automaton is too large"));
+                        }
+                        index.insert(key.clone(), keys.len());
+                        keys.push(key);
+                        keys.len() - 1
+                    }
+                };
+                cells.push(next);
+            }
+            state += 1;
+        }
+
+        let eoi = keys
+            .iter()
+            .map(|(states, matched)| {
+                let mut ids = matched.clone();
+                ids.extend(
+                    states
+                        .iter()
+                        .enumerate()
+                        .filter(|&(id, &s)| s != 0 && fsms[id].table.eoi[s])
+                        .map(|(id, _)| id),
+                );
+                ids.sort();
+                ids
+            })
+            .collect();
+        Ok(Self {
+            len: fsms.len(),
+            classes: CharClasses::new(&segments, reps.len()),
+            ts: Transitions::new(&cells),
+            done: keys
+                .iter()
+                .map(|(s, _)| s.iter().all(|&s| s == 0))
+                .collect(),
+            matched: keys.into_iter().map(|(_, matched)| matched).collect(),
+            eoi,
+        })
+    }
+
+    /// Ids of the patterns matching `input`, in increasing order.
+    pub fn matches(&self, input: &str) -> Vec<usize> {
+        let mut state = 0;
+        for c in input.chars() {
+            if self.done[state] {
+                return self.matched[state].clone();
+            }
+            state = self
+                .ts
+                .get(state * self.classes.count() + self.classes.get(c));
+        }
+        self.eoi[state].clone()
+    }
+
+    pub fn is_match(&self, input: &str) -> bool {
+        !self.matches(input).is_empty()
+    }
+
+    /// Number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of states of the product automaton.
+    pub fn state_count(&self) -> usize {
+        self.done.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agrees_with_single_patterns() {
+        let patterns = ["a+$", "ab", "[a-c]*x", "", "\\d{2}", "b(?i)b+$", "é+"];
+        let set = RegexSet::new(patterns).unwrap();
+        let fsms = patterns.map(|p| Fsm::compile(p).unwrap());
+        for input in [
+            "", "a", "aaa", "ab", "abx", "cx", "12", "1", "bBb", "ééa", "x",
+        ] {
+            let expected = (0..fsms.len())
+                .filter(|&id| fsms[id].match_str(input))
+                .collect::<Vec<_>>();
+            assert_eq!(set.matches(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn empty_set() {
+        let set = RegexSet::new(Vec::<String>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("abc"));
+        assert_eq!(set.state_count(), 1);
+    }
+
+    #[test]
+    fn compile_error() {
+        let e = RegexSet::new(["a", "b{2,1}"]).err().unwrap();
+        assert_eq!(e.msg, "invalid repetition range");
+    }
+}
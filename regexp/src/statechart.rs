@@ -0,0 +1,479 @@
+//! Hierarchical state machines (statecharts). States nest: a compound state
+//! has exactly one active child, a parallel state runs all of its children
+//! as orthogonal regions, and an event the active state does not handle
+//! bubbles up to its parent.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+
+/// What a compound state remembers when it is left, to be restored the next
+/// time it is entered without naming one of its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum History {
+    /// The child which was active.
+    Shallow,
+    /// Every descendant which was active.
+    Deep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Compound,
+    Parallel,
+}
+
+#[derive(Debug, Clone)]
+struct Node<S> {
+    id: S,
+    kind: Kind,
+    parent: Option<S>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatechartError {
+    pub msg: &'static str,
+}
+
+impl StatechartError {
+    fn new(msg: &'static str) -> Self {
+        Self { msg }
+    }
+}
+
+impl fmt::Display for StatechartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for StatechartError {}
+
+/// Declares the states and transitions of a `Statechart`. Parents must be
+/// declared before their children.
+#[derive(Debug, Clone)]
+pub struct StatechartBuilder<S, E> {
+    nodes: Vec<Node<S>>,
+    initial: Vec<(S, S)>,
+    history: Vec<(S, History)>,
+    transitions: Vec<(S, E, S)>,
+}
+
+impl<S, E> StatechartBuilder<S, E>
+where
+    S: Copy + Eq + Hash,
+    E: Copy + Eq,
+{
+    /// A chart whose outermost state is the compound state `root`.
+    pub fn new(root: S) -> Self {
+        Self {
+            nodes: vec![Node {
+                id: root,
+                kind: Kind::Compound,
+                parent: None,
+            }],
+            initial: Vec::new(),
+            history: Vec::new(),
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Adds `state` as a child of `parent`. A state without children is a
+    /// leaf; with children, it is a compound state entering its first child
+    /// unless `initial` says otherwise.
+    pub fn state(&mut self, state: S, parent: S) -> &mut Self {
+        self.nodes.push(Node {
+            id: state,
+            kind: Kind::Compound,
+            parent: Some(parent),
+        });
+        self
+    }
+
+    /// Adds `state` as a child of `parent`, with every child of `state`
+    /// active at once.
+    pub fn parallel(&mut self, state: S, parent: S) -> &mut Self {
+        self.nodes.push(Node {
+            id: state,
+            kind: Kind::Parallel,
+            parent: Some(parent),
+        });
+        self
+    }
+
+    /// Makes `child` the state entered first in `state`.
+    pub fn initial(&mut self, state: S, child: S) -> &mut Self {
+        self.initial.push((state, child));
+        self
+    }
+
+    /// Makes the compound `state` remember its active states when it is left.
+    pub fn history(&mut self, state: S, history: History) -> &mut Self {
+        self.history.push((state, history));
+        self
+    }
+
+    /// Moves from `from` to `to` on `event`, leaving and entering again every
+    /// state between them and their closest common compound ancestor.
+    pub fn transition(&mut self, from: S, event: E, to: S) -> &mut Self {
+        self.transitions.push((from, event, to));
+        self
+    }
+
+    /// Checks the declarations and enters the initial states.
+    pub fn build(&self) -> Result<Statechart<S, E>, StatechartError> {
+        let mut index = HashMap::new();
+        let mut parents = Vec::with_capacity(self.nodes.len());
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if index.insert(node.id, i).is_some() {
+                return Err(StatechartError::new("duplicate state"));
+            }
+            let parent = match node.parent {
+                Some(parent) => Some(
+                    *index
+                        .get(&parent)
+                        .ok_or(StatechartError::new("unknown parent state"))?,
+                ),
+                None => None,
+            };
+            if let Some(parent) = parent {
+                children[parent].push(i);
+            }
+            parents.push(parent);
+        }
+        let lookup = |state: &S| {
+            index
+                .get(state)
+                .copied()
+                .ok_or(StatechartError::new("unknown state"))
+        };
+
+        let mut initial = children
+            .iter()
+            .map(|c| c.first().copied())
+            .collect::<Vec<_>>();
+        for (state, child) in self.initial.iter() {
+            let (state, child) = (lookup(state)?, lookup(child)?);
+            if parents[child] != Some(state) || self.nodes[state].kind != Kind::Compound {
+                return Err(StatechartError::new(
+                    "initial state is not a child of a compound state",
+                ));
+            }
+            initial[state] = Some(child);
+        }
+        let mut history = vec![None; self.nodes.len()];
+        for (state, kind) in self.history.iter() {
+            let state = lookup(state)?;
+            if self.nodes[state].kind != Kind::Compound || children[state].is_empty() {
+                return Err(StatechartError::new("history needs a compound state"));
+            }
+            history[state] = Some(*kind);
+        }
+        let mut transitions = Vec::with_capacity(self.transitions.len());
+        for (from, event, to) in self.transitions.iter() {
+            transitions.push((lookup(from)?, *event, lookup(to)?));
+        }
+
+        let mut chart = Statechart {
+            ids: self.nodes.iter().map(|node| node.id).collect(),
+            kinds: self.nodes.iter().map(|node| node.kind).collect(),
+            parents,
+            children,
+            initial,
+            history,
+            saved: vec![None; self.nodes.len()],
+            transitions,
+            active: vec![false; self.nodes.len()],
+        };
+        chart.active[0] = true;
+        chart.complete(0);
+        Ok(chart)
+    }
+}
+
+/// A running statechart. States are numbered in declaration order, so a
+/// parent always comes before its children.
+#[derive(Debug, Clone)]
+pub struct Statechart<S, E> {
+    ids: Vec<S>,
+    kinds: Vec<Kind>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    initial: Vec<Option<usize>>,
+    history: Vec<Option<History>>,
+    /// The states to restore when entering a state with history.
+    saved: Vec<Option<Vec<usize>>>,
+    transitions: Vec<(usize, E, usize)>,
+    active: Vec<bool>,
+}
+
+impl<S, E> Statechart<S, E>
+where
+    S: Copy + Eq + Hash,
+    E: Copy + Eq,
+{
+    pub fn is_active(&self, state: S) -> bool {
+        self.ids
+            .iter()
+            .position(|&id| id == state)
+            .is_some_and(|i| self.active[i])
+    }
+
+    /// The active leaf states, one for every active region, in declaration
+    /// order.
+    pub fn active(&self) -> Vec<S> {
+        (0..self.ids.len())
+            .filter(|&i| self.active[i] && self.children[i].is_empty())
+            .map(|i| self.ids[i])
+            .collect()
+    }
+
+    /// Every active leaf looks for a transition on `event` in itself, then
+    /// in its ancestors, and the transitions found are taken one after the
+    /// other. A transition whose state was left by an earlier one is skipped.
+    /// Returns whether any transition was taken.
+    pub fn handle(&mut self, event: E) -> bool {
+        let mut selected = Vec::new();
+        for leaf in (0..self.ids.len()).filter(|&i| self.active[i] && self.children[i].is_empty()) {
+            let found = self.ancestors(leaf).find_map(|state| {
+                self.transitions
+                    .iter()
+                    .position(|&(from, on, _)| from == state && on == event)
+            });
+            if let Some(t) = found {
+                if !selected.contains(&t) {
+                    selected.push(t);
+                }
+            }
+        }
+
+        let mut taken = false;
+        for t in selected {
+            let (from, _, to) = self.transitions[t];
+            if self.active[from] {
+                self.take(from, to);
+                taken = true;
+            }
+        }
+        taken
+    }
+
+    /// `state` followed by its ancestors, innermost first.
+    fn ancestors(&self, state: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(state), |&i| self.parents[i])
+    }
+
+    fn is_descendant(&self, state: usize, of: usize) -> bool {
+        self.ancestors(state).skip(1).any(|i| i == of)
+    }
+
+    fn take(&mut self, from: usize, to: usize) {
+        // The closest compound state strictly containing both ends.
+        let domain = self
+            .ancestors(from)
+            .skip(1)
+            .find(|&a| self.kinds[a] == Kind::Compound && self.is_descendant(to, a))
+            .unwrap_or(0);
+
+        let exited = (0..self.ids.len())
+            .filter(|&i| self.active[i] && self.is_descendant(i, domain))
+            .collect::<Vec<_>>();
+        for &state in exited.iter() {
+            let remembered = match self.history[state] {
+                Some(History::Shallow) => exited
+                    .iter()
+                    .copied()
+                    .filter(|&i| self.parents[i] == Some(state))
+                    .collect(),
+                Some(History::Deep) => exited
+                    .iter()
+                    .copied()
+                    .filter(|&i| self.is_descendant(i, state))
+                    .collect(),
+                None => continue,
+            };
+            self.saved[state] = Some(remembered);
+        }
+        for state in exited {
+            self.active[state] = false;
+        }
+
+        for state in self
+            .ancestors(to)
+            .take_while(|&i| i != domain)
+            .collect::<Vec<_>>()
+        {
+            self.active[state] = true;
+        }
+        self.complete(domain);
+    }
+
+    /// Enters the children `state` needs: every region of a parallel state,
+    /// and the remembered, or else the initial, child of a compound state
+    /// without an active one.
+    fn complete(&mut self, state: usize) {
+        let children = self.children[state].clone();
+        match self.kinds[state] {
+            Kind::Parallel => {
+                for &child in children.iter() {
+                    self.active[child] = true;
+                }
+            }
+            Kind::Compound if children.iter().all(|&c| !self.active[c]) => {
+                match &self.saved[state] {
+                    Some(saved) => {
+                        for &i in saved.iter() {
+                            self.active[i] = true;
+                        }
+                    }
+                    None => {
+                        if let Some(initial) = self.initial[state] {
+                            self.active[initial] = true;
+                        }
+                    }
+                }
+            }
+            Kind::Compound => {}
+        }
+        for child in children {
+            if self.active[child] {
+                self.complete(child);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum State {
+        Root,
+        Operational,
+        Locked,
+        Unlocked,
+        Idle,
+        Spinning,
+        Maintenance,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Coin,
+        Push,
+        Passed,
+        Timeout,
+        Service,
+        Resume,
+    }
+
+    fn turnstile(history: History) -> Statechart<State, Event> {
+        use Event::*;
+        use State::*;
+
+        StatechartBuilder::new(Root)
+            .state(Operational, Root)
+            .state(Locked, Operational)
+            .state(Unlocked, Operational)
+            .state(Idle, Unlocked)
+            .state(Spinning, Unlocked)
+            .state(Maintenance, Root)
+            .history(Operational, history)
+            .transition(Locked, Coin, Unlocked)
+            .transition(Idle, Push, Spinning)
+            .transition(Spinning, Passed, Locked)
+            .transition(Unlocked, Timeout, Locked)
+            .transition(Operational, Service, Maintenance)
+            .transition(Maintenance, Resume, Operational)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn turnstile_with_maintenance() {
+        use Event::*;
+        use State::*;
+
+        let mut chart = turnstile(History::Deep);
+        assert_eq!(chart.active(), [Locked]);
+        assert!(!chart.handle(Push));
+        assert!(chart.handle(Coin));
+        assert_eq!(chart.active(), [Idle]);
+        assert!(chart.is_active(Unlocked) && chart.is_active(Operational));
+        assert!(chart.handle(Push));
+        assert_eq!(chart.active(), [Spinning]);
+
+        // Neither `Spinning` nor `Unlocked` handle `Service`: it bubbles up
+        // to `Operational`.
+        assert!(chart.handle(Service));
+        assert_eq!(chart.active(), [Maintenance]);
+        assert!(!chart.handle(Coin));
+        assert!(chart.handle(Resume));
+        assert_eq!(chart.active(), [Spinning]);
+        assert!(chart.handle(Passed));
+        assert_eq!(chart.active(), [Locked]);
+
+        let mut chart = turnstile(History::Shallow);
+        chart.handle(Coin);
+        chart.handle(Push);
+        chart.handle(Service);
+        chart.handle(Resume);
+        assert_eq!(chart.active(), [Idle]);
+        assert!(chart.handle(Timeout));
+        assert_eq!(chart.active(), [Locked]);
+    }
+
+    #[test]
+    fn orthogonal_regions() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        enum S {
+            Root,
+            Device,
+            Gate,
+            Closed,
+            Open,
+            Light,
+            Off,
+            On,
+            Broken,
+        }
+        use S::*;
+
+        let mut chart = StatechartBuilder::new(Root)
+            .parallel(Device, Root)
+            .state(Gate, Device)
+            .state(Closed, Gate)
+            .state(Open, Gate)
+            .state(Light, Device)
+            .state(Off, Light)
+            .state(On, Light)
+            .state(Broken, Root)
+            .initial(Light, On)
+            .transition(Closed, "toggle", Open)
+            .transition(Open, "toggle", Closed)
+            .transition(On, "toggle", Off)
+            .transition(Device, "fail", Broken)
+            .transition(Broken, "repair", Device)
+            .build()
+            .unwrap();
+        assert_eq!(chart.active(), [Closed, On]);
+        assert!(chart.handle("toggle"));
+        assert_eq!(chart.active(), [Open, Off]);
+        assert!(chart.handle("toggle"));
+        assert_eq!(chart.active(), [Closed, Off]);
+        assert!(chart.handle("fail"));
+        assert_eq!(chart.active(), [Broken]);
+        assert!(chart.handle("repair"));
+        assert_eq!(chart.active(), [Closed, On]);
+
+        let mut builder = StatechartBuilder::<S, &str>::new(Root);
+        builder.state(Gate, Device);
+        assert_eq!(builder.build().unwrap_err().msg, "unknown parent state");
+        let mut builder = StatechartBuilder::<S, &str>::new(Root);
+        builder.state(Gate, Root).transition(Gate, "x", Open);
+        assert_eq!(builder.build().unwrap_err().msg, "unknown state");
+    }
+}
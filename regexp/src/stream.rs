@@ -0,0 +1,250 @@
+use std::io::{self, ErrorKind, Read};
+
+use crate::Fsm;
+
+/// Size of the buffer `Fsm::is_match_reader` reads into.
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Matches input which arrives in chunks of bytes. Only the current state and
+/// the bytes of a character split between two chunks are kept, so the input
+/// never has to be held in memory as a whole.
+///
+/// Bytes which are not valid UTF-8 are matched as `U+FFFD`.
+///
+/// ```
+/// use regexp::Fsm;
+///
+/// let fsm = Fsm::compile("ab+c$").unwrap();
+/// let mut matcher = fsm.matcher();
+/// assert_eq!(matcher.feed(b"ab"), None);
+/// assert_eq!(matcher.feed(b"bbc"), None);
+/// assert!(matcher.finish());
+/// ```
+#[derive(Clone)]
+pub struct Matcher<'a> {
+    fsm: &'a Fsm,
+    state: usize,
+    pending: [u8; 4],
+    pending_len: usize,
+}
+
+impl Fsm {
+    /// Starts matching input which will be fed in chunks.
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher {
+            fsm: self,
+            state: 1,
+            pending: [0; 4],
+            pending_len: 0,
+        }
+    }
+
+    /// Matches everything `reader` yields, reading it in chunks and stopping
+    /// as soon as the answer is known.
+    pub fn is_match_reader(&self, mut reader: impl Read) -> io::Result<bool> {
+        let mut matcher = self.matcher();
+        let mut buf = [0; READ_BUFFER_SIZE];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(matcher.finish()),
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(matched) = matcher.feed(&buf[..n]) {
+                return Ok(matched);
+            }
+        }
+    }
+}
+
+impl Matcher<'_> {
+    /// Runs the next chunk of input. Returns `Some` once the answer no
+    /// longer depends on the input still to come, and `None` while it does.
+    pub fn feed(&mut self, bytes: &[u8]) -> Option<bool> {
+        for &b in bytes {
+            if let Some(matched) = self.decided() {
+                return Some(matched);
+            }
+            self.push_byte(b);
+        }
+        self.decided()
+    }
+
+    /// Ends the input and returns whether it matched.
+    pub fn finish(&mut self) -> bool {
+        if self.pending_len > 0 {
+            self.pending_len = 0;
+            self.step(char::REPLACEMENT_CHARACTER);
+        }
+        let table = &self.fsm.table;
+        self.state == table.accept || table.eoi[self.state]
+    }
+
+    /// Starts over with empty input.
+    pub fn reset(&mut self) {
+        self.state = 1;
+        self.pending_len = 0;
+    }
+
+    fn decided(&self) -> Option<bool> {
+        if self.state == 0 {
+            Some(false)
+        } else if self.state == self.fsm.table.accept {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    fn step(&mut self, c: char) {
+        if self.decided().is_none() {
+            self.state = self.fsm.table.next(self.state, c);
+        }
+    }
+
+    /// Decodes UTF-8 one byte at a time. A sequence cut short by a byte
+    /// which cannot continue it is replaced, and that byte starts over, so
+    /// each maximal invalid part gives one `U+FFFD` as with
+    /// `String::from_utf8_lossy`.
+    fn push_byte(&mut self, b: u8) {
+        if self.pending_len == 0 {
+            match sequence_len(b) {
+                1 => self.step(b as char),
+                0 => self.step(char::REPLACEMENT_CHARACTER),
+                _ => {
+                    self.pending[0] = b;
+                    self.pending_len = 1;
+                }
+            }
+            return;
+        }
+        if !continues(&self.pending[..self.pending_len], b) {
+            self.pending_len = 0;
+            self.step(char::REPLACEMENT_CHARACTER);
+            self.push_byte(b);
+            return;
+        }
+        self.pending[self.pending_len] = b;
+        self.pending_len += 1;
+        let len = sequence_len(self.pending[0]);
+        if self.pending_len == len {
+            self.pending_len = 0;
+            let c = std::str::from_utf8(&self.pending[..len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .expect("every byte was checked by `continues`");
+            self.step(c);
+        }
+    }
+}
+
+/// Length of the UTF-8 sequence `first` starts, or `0` if it cannot start
+/// one.
+fn sequence_len(first: u8) -> usize {
+    match first {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 0,
+    }
+}
+
+/// Whether `b` can follow the bytes of the unfinished sequence `pending`.
+/// The second byte is restricted further after some first bytes, which
+/// rules out overlong forms, surrogates and values past `U+10FFFF`.
+fn continues(pending: &[u8], b: u8) -> bool {
+    let range = match pending {
+        [0xE0] => 0xA0..=0xBF,
+        [0xED] => 0x80..=0x9F,
+        [0xF0] => 0x90..=0xBF,
+        [0xF4] => 0x80..=0x8F,
+        _ => 0x80..=0xBF,
+    };
+    range.contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_boundaries() {
+        let fsm = Fsm::compile("\\p{Alphabetic}+ 😀+$").unwrap();
+        for input in ["中é 😀😀", "中é 😀x", "abc"] {
+            let bytes = input.as_bytes();
+            for split in 0..=bytes.len() {
+                let mut matcher = fsm.matcher();
+                let early = matcher
+                    .feed(&bytes[..split])
+                    .or_else(|| matcher.feed(&bytes[split..]));
+                let matched = early.unwrap_or_else(|| matcher.finish());
+                assert_eq!(matched, fsm.match_str(input), "{:?} at {}", input, split);
+            }
+        }
+    }
+
+    #[test]
+    fn decided_early() {
+        let fsm = Fsm::compile("ab").unwrap();
+        let mut matcher = fsm.matcher();
+        assert_eq!(matcher.feed(b"a"), None);
+        assert_eq!(matcher.feed(b"bzzz"), Some(true));
+        matcher.reset();
+        assert_eq!(matcher.feed(b"x"), Some(false));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        for (input, replaced) in [
+            (&b"a\xFFb"[..], 1),
+            (b"a\xE4\xB8b", 1),
+            (b"a\xC0\x80b", 2),
+            (b"a\xED\xA0\x80b", 3),
+            (b"a\xF0\x80\x80b", 3),
+            (b"a\xE0\x80b", 2),
+            (b"a\xF4\x90\x80\x80b", 4),
+            (b"a\xF0\x9F\x98b", 1),
+        ] {
+            // The same replacements as `from_utf8_lossy`, no more and no less.
+            let lossy = String::from_utf8_lossy(input);
+            assert_eq!(lossy.matches('\u{FFFD}').count(), replaced);
+            let fsm = Fsm::compile(&format!("{}$", lossy)).unwrap();
+            let mut matcher = fsm.matcher();
+            assert_eq!(matcher.feed(input), None);
+            assert!(matcher.finish(), "{:?}", input);
+        }
+        let truncated = Fsm::compile("a\u{FFFD}$").unwrap();
+        let mut matcher = truncated.matcher();
+        matcher.feed(b"a\xE4");
+        assert!(matcher.finish());
+    }
+
+    /// Yields its input in small chunks, and fails if it is read past it.
+    struct Chunks<'a>(&'a [u8]);
+
+    impl Read for Chunks<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(3);
+            if n == 0 {
+                return Err(io::Error::other("read past the end"));
+            }
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn match_reader() {
+        let fsm = Fsm::compile("[a-z]+\\d").unwrap();
+        assert!(fsm.is_match_reader(Chunks(b"abcdefg1")).unwrap());
+        assert!(!fsm.is_match_reader(Chunks(b"abc-")).unwrap());
+        assert!(fsm.is_match_reader(Chunks(b"abc")).is_err());
+        assert!(!fsm.is_match_reader(&b"abc"[..]).unwrap());
+        assert!(fsm
+            .is_match_reader(io::repeat(b'a').take(100_000).chain(&b"9"[..]))
+            .unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+use std::mem::size_of;
+
+const ASCII_SIZE: usize = 128;
+
+/// Maps every character to its alphabet equivalence class. Characters in the
+/// same class take the same transition in every state, so the transition
+/// table only needs one entry per class instead of one per character.
+#[derive(Debug, Clone)]
+pub struct CharClasses {
+    ascii: [u32; ASCII_SIZE],
+    /// Start of every segment above ASCII and the class it belongs to.
+    ranges: Vec<(char, u32)>,
+    count: usize,
+}
+
+impl CharClasses {
+    /// `segments` holds the start of each segment (the first one being
+    /// `'\0'`) with the class of the characters up to the next start.
+    pub fn new(segments: &[(char, u32)], count: usize) -> Self {
+        let mut ascii = [0; ASCII_SIZE];
+        for (i, class) in ascii.iter_mut().enumerate() {
+            let j = segments.partition_point(|&(start, _)| start as usize <= i);
+            *class = segments[j - 1].1;
+        }
+
+        let mut ranges: Vec<(char, u32)> = Vec::new();
+        for &(start, class) in segments.iter() {
+            if (start as usize) < ASCII_SIZE {
+                ranges.clear();
+                ranges.push(('\u{80}', class));
+            } else if ranges.last().map(|r| r.1) != Some(class) {
+                ranges.push((start, class));
+            }
+        }
+
+        Self {
+            ascii,
+            ranges,
+            count,
+        }
+    }
+
+    pub fn get(&self, c: char) -> usize {
+        if (c as usize) < ASCII_SIZE {
+            return self.ascii[c as usize] as usize;
+        }
+        let i = self.ranges.partition_point(|&(start, _)| start <= c);
+        self.ranges[i - 1].1 as usize
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The segments this was built from, with neighbours of the same class
+    /// merged.
+    pub fn segments(&self) -> Vec<(char, u32)> {
+        let mut segments: Vec<(char, u32)> = Vec::new();
+        let ascii = self
+            .ascii
+            .iter()
+            .enumerate()
+            .map(|(i, &class)| (i as u8 as char, class));
+        for (start, class) in ascii.chain(self.ranges.iter().copied()) {
+            if segments.last().map(|s| s.1) != Some(class) {
+                segments.push((start, class));
+            }
+        }
+        segments
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.ranges.capacity() * size_of::<(char, u32)>()
+    }
+}
+
+/// Transitions stored as `states × classes`, using the smallest integer type
+/// able to hold every state index.
+#[derive(Debug, Clone)]
+pub enum Transitions {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Transitions {
+    pub fn new(cells: &[usize]) -> Self {
+        let max = cells.iter().copied().max().unwrap_or(0);
+        if max <= u8::MAX as usize {
+            Self::U8(cells.iter().map(|&c| c as u8).collect())
+        } else if max <= u16::MAX as usize {
+            Self::U16(cells.iter().map(|&c| c as u16).collect())
+        } else {
+            Self::U32(cells.iter().map(|&c| c as u32).collect())
+        }
+    }
+
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            Self::U8(ts) => ts[i] as usize,
+            Self::U16(ts) => ts[i] as usize,
+            Self::U32(ts) => ts[i] as usize,
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Self::U8(ts) => ts.capacity() * size_of::<u8>(),
+                Self::U16(ts) => ts.capacity() * size_of::<u16>(),
+                Self::U32(ts) => ts.capacity() * size_of::<u32>(),
+            }
+    }
+}
+
+/// The compressed form of an `Fsm` used for matching. Look back transitions
+/// are already resolved, so every transition consumes exactly one character.
+/// State `0` fails, state `accept` accepts, and `eoi[state]` tells whether
+/// running out of input in `state` accepts.
+#[derive(Debug, Clone)]
+pub struct FsmTable {
+    pub classes: CharClasses,
+    pub ts: Transitions,
+    pub eoi: Vec<bool>,
+    pub accept: usize,
+}
+
+impl FsmTable {
+    pub fn next(&self, state: usize, c: char) -> usize {
+        self.ts
+            .get(state * self.classes.count() + self.classes.get(c))
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.classes.memory_usage() + self.ts.memory_usage() + self.eoi.capacity()
+    }
+}
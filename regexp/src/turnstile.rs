@@ -1,41 +1,87 @@
-#[derive(Debug)]
-pub enum State {
-    Locked,
-    Unlocked,
-}
+use crate::extended::{Machine, Transition};
+use crate::machine::StateMachine;
+use crate::state_machine;
+
+state_machine! {
+    pub machine Turnstile {
+        states State { Locked, Unlocked }
+        events Event { Coin, Push }
+        initial Locked;
 
-#[derive(Debug)]
-pub enum Event {
-    Coin,
-    Push,
+        Locked + Coin => Unlocked,
+        Locked + Push => Locked,
+        Unlocked + Coin => Unlocked,
+        Unlocked + Push => Locked,
+    }
 }
 
-const EVENTS_COUNT: usize = 2;
-const STATES_COUNT: usize = 2;
+pub fn next_state(state: State, event: Event) -> State {
+    Turnstile::next_state(state, event)
+}
 
-const FSM: [[usize; EVENTS_COUNT]; STATES_COUNT] = [
-    /*      COIN                      PUSH          */
-    [State::Unlocked as usize, State::Locked as usize], // LOCKED
-    [State::Unlocked as usize, State::Locked as usize], // UNLOCKED
-];
+/// What the turnstile signals when an event is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// The coin was taken and the arm released.
+    Accepted,
+    /// The turnstile was already unlocked, so the coin is given back.
+    Refunded,
+    /// The coin box is full, so the coin is given back and the arm stays locked.
+    Full,
+    /// Someone pushed the locked arm.
+    Alarm,
+}
 
-pub fn state_to_str(state: &State) -> &'static str {
-    match state {
-        State::Locked => "Locked",
-        State::Unlocked => "Unlocked",
-    }
+/// The extended state of a coin-counting turnstile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counter {
+    /// Coins in the box.
+    pub coins: u32,
+    /// Number of coins the box holds, or `None` for no limit.
+    pub capacity: Option<u32>,
+    /// Pushes rejected while the turnstile was locked.
+    pub rejected_pushes: u32,
+    /// Number of times someone walked through.
+    pub passages: u32,
 }
 
-fn usize_to_state(state: usize) -> State {
-    match state {
-        0 => State::Locked,
-        1 => State::Unlocked,
-        _ => unreachable!(),
+impl Counter {
+    fn has_room(&self) -> bool {
+        self.capacity.is_none_or(|capacity| self.coins < capacity)
     }
 }
 
-pub fn next_state(state: State, event: Event) -> State {
-    usize_to_state(FSM[state as usize][event as usize])
+/// A turnstile which counts the coins it takes and raises an alarm on
+/// pushes while locked.
+pub fn coin_counter(counter: Counter) -> Machine<State, Event, Counter, Output> {
+    let mut machine = Machine::new(Turnstile::INITIAL, counter);
+    machine
+        .transition(
+            Transition::new(State::Locked, Event::Coin, State::Unlocked)
+                .guard(Counter::has_room)
+                .action(|counter: &mut Counter| {
+                    counter.coins += 1;
+                    Output::Accepted
+                }),
+        )
+        .transition(
+            Transition::new(State::Locked, Event::Coin, State::Locked).action(|_| Output::Full),
+        )
+        .transition(
+            Transition::new(State::Locked, Event::Push, State::Locked).action(
+                |counter: &mut Counter| {
+                    counter.rejected_pushes += 1;
+                    Output::Alarm
+                },
+            ),
+        )
+        .transition(
+            Transition::new(State::Unlocked, Event::Coin, State::Unlocked)
+                .action(|_| Output::Refunded),
+        )
+        .transition(Transition::new(State::Unlocked, Event::Push, State::Locked))
+        .on_exit(State::Unlocked, |counter| counter.passages += 1);
+    machine
 }
 
 #[cfg(test)]
@@ -54,9 +100,40 @@ mod tests {
             match line {
                 "coin" => state = next_state(state, Event::Coin),
                 "push" => state = next_state(state, Event::Push),
-                _ => assert!(false),
+                _ => unreachable!(),
             }
-            assert_eq!(state_to_str(&state), states[i]);
+            assert_eq!(state.to_string(), states[i]);
         }
     }
+
+    #[test]
+    fn count_coins() {
+        let mut turnstile = coin_counter(Counter {
+            capacity: Some(2),
+            ..Counter::default()
+        });
+        let events = [
+            (Event::Push, Some(Output::Alarm), State::Locked),
+            (Event::Coin, Some(Output::Accepted), State::Unlocked),
+            (Event::Coin, Some(Output::Refunded), State::Unlocked),
+            (Event::Push, None, State::Locked),
+            (Event::Coin, Some(Output::Accepted), State::Unlocked),
+            (Event::Push, None, State::Locked),
+            (Event::Coin, Some(Output::Full), State::Locked),
+            (Event::Push, Some(Output::Alarm), State::Locked),
+        ];
+        for (event, output, state) in events {
+            assert_eq!(turnstile.handle(event), Ok(output));
+            assert_eq!(turnstile.state(), state);
+        }
+        assert_eq!(
+            turnstile.context(),
+            &Counter {
+                coins: 2,
+                capacity: Some(2),
+                rejected_pushes: 2,
+                passages: 2,
+            }
+        );
+    }
 }